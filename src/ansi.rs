@@ -0,0 +1,36 @@
+use unicode_width::UnicodeWidthStr;
+
+/// `text` without ansi escape sequences
+pub fn strip(text: &str) -> String {
+    nu_utils::strip_ansi_string_likely(text.to_string())
+}
+
+/// width of the text in terminal cells (ansi escape sequences take up no space)
+pub fn display_width(text: &str) -> usize {
+    strip(text).width()
+}
+
+/// columns of the terminal (80 if it is not a terminal)
+pub fn terminal_columns() -> usize {
+    crossterm::terminal::size()
+        .map(|(columns, _rows)| columns as usize)
+        .unwrap_or(80)
+}
+
+/// rows the text takes up when printed from the start of a line (including wrapping), and the
+/// width of the last row
+pub fn measure(text: &str, columns: usize) -> (usize, usize) {
+    let columns = columns.max(1);
+    let mut rows = 0;
+    let mut last_width = 0;
+    for line in strip(text).split('\n') {
+        let width = line.width();
+        rows += 1 + width.saturating_sub(1) / columns;
+        last_width = if width == 0 {
+            0
+        } else {
+            (width - 1) % columns + 1
+        };
+    }
+    (rows, last_width)
+}
@@ -0,0 +1,131 @@
+//! `NuInstance::builder()`: set up a interpreter for embedding.
+//!
+//! ```
+//! let mut ni = heretic_nu::NuInstance::builder()
+//!     .with_std()
+//!     .with_config(heretic_nu::ConfigFiles::default())
+//!     .with_var("name", nu_protocol::Value::test_string("world"))
+//!     .build()?;
+//! assert_eq!(ni.eval_to_string("$'hello ($name)'")?, "hello world");
+//! # Ok::<(), nu_protocol::ShellError>(())
+//! ```
+
+use std::path::PathBuf;
+
+use nu_protocol::{engine::Command, PipelineData, ShellError, Signature, Value};
+
+use crate::{
+    fn_command::{FnArgs, FnCommand},
+    ConfigFiles, NuInstance,
+};
+
+/// see `NuInstance::builder`
+#[derive(Default)]
+pub struct NuInstanceBuilder {
+    #[cfg(feature = "nu_std")]
+    std: bool,
+    config: Option<ConfigFiles>,
+    cwd: Option<PathBuf>,
+    env_vars: Vec<(String, Value)>,
+    vars: Vec<(String, Value)>,
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl NuInstance {
+    /// a instance without the standard library and without any config (not even the default
+    /// config, so there is no `$env.config` customisation, prompt, or input function)
+    pub fn builder() -> NuInstanceBuilder {
+        NuInstanceBuilder::default()
+    }
+}
+
+impl NuInstanceBuilder {
+    /// load the standard library (`use std`)
+    #[cfg(feature = "nu_std")]
+    pub fn with_std(mut self) -> Self {
+        self.std = true;
+        self
+    }
+
+    /// load the default config and the given user config files
+    /// (`ConfigFiles::default()`: only the default config)
+    pub fn with_config(mut self, files: ConfigFiles) -> Self {
+        self.config = Some(files);
+        self
+    }
+
+    /// working directory (`$env.PWD`), default: the one of the process
+    pub fn with_cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    /// set before the config is loaded
+    pub fn with_env_var(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.env_vars.push((name.into(), value));
+        self
+    }
+
+    /// see `NuInstance::set_var`, set before the config is loaded
+    pub fn with_var(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.vars.push((name.into(), value));
+        self
+    }
+
+    /// registered before the config is loaded, so the config can use it
+    pub fn with_command(mut self, command: Box<dyn Command>) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// see `NuInstance::register_fn`
+    pub fn with_fn(
+        mut self,
+        name: &str,
+        mut signature: Signature,
+        callback: impl Fn(&mut FnArgs, PipelineData) -> Result<Value, ShellError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        signature.name = name.into();
+        self.commands
+            .push(Box::new(FnCommand::new(signature, callback)));
+        self
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn build(self) -> Result<NuInstance, ShellError> {
+        let mut ni = NuInstance::new()?;
+        #[cfg(feature = "nu_std")]
+        if self.std {
+            ni.add_stdlib()?;
+        }
+        if let Some(cwd) = self.cwd {
+            let cwd = std::fs::canonicalize(&cwd).map_err(|e| ShellError::GenericError {
+                error: "Invalid working directory".into(),
+                msg: format!("{}: {e}", cwd.display()),
+                span: None,
+                help: None,
+                inner: vec![],
+            })?;
+            ni.set_env_var(
+                "PWD",
+                Value::string(cwd.to_string_lossy(), nu_protocol::Span::unknown()),
+            );
+        }
+        for (name, value) in self.env_vars {
+            ni.set_env_var(&name, value);
+        }
+        for (name, value) in self.vars {
+            ni.set_var(&name, value)?;
+        }
+        if !self.commands.is_empty() {
+            ni.append_commands(self.commands)?;
+        }
+        if let Some(files) = self.config {
+            ni.try_load_all_configs(&files)?;
+        }
+        Ok(ni)
+    }
+}
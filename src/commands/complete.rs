@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use nu_cli::{NuCompleter, SuggestionKind};
+use nu_engine::command_prelude::*;
+use nu_protocol::PipelineData;
+
+#[derive(Clone)]
+pub struct HereticComplete;
+
+/// char-offset -> byte-offset (clamped to the end of the line)
+fn byte_offset(line: &str, char_offset: usize) -> usize {
+    line.char_indices()
+        .nth(char_offset)
+        .map(|(i, _)| i)
+        .unwrap_or(line.len())
+}
+
+/// byte-offset -> char-offset
+fn char_offset(line: &str, byte_offset: usize) -> usize {
+    line.char_indices()
+        .take_while(|(i, _)| *i < byte_offset)
+        .count()
+}
+
+/// a completion suggestion, spans are char-offsets within the line
+#[derive(Clone, Debug)]
+pub struct Completion {
+    pub value: String,
+    pub description: Option<String>,
+    pub kind: Option<&'static str>,
+    pub start: usize,
+    pub end: usize,
+    pub append_whitespace: bool,
+}
+
+/// stable name of a suggestion kind (shown in the `kind` column)
+fn kind_name(kind: &SuggestionKind) -> &'static str {
+    match kind {
+        SuggestionKind::Command(..) => "command",
+        SuggestionKind::Value(_) => "value",
+        SuggestionKind::CellPath => "cellpath",
+        SuggestionKind::Directory => "directory",
+        SuggestionKind::File => "file",
+        SuggestionKind::Flag => "flag",
+        SuggestionKind::Module => "module",
+        SuggestionKind::Operator => "operator",
+        SuggestionKind::Variable => "variable",
+    }
+}
+
+/// run nu's completer against the live engine-state (`cursor` is a char-offset)
+pub fn completions(
+    engine_state: &EngineState,
+    stack: &Stack,
+    line: &str,
+    cursor: usize,
+) -> Vec<Completion> {
+    let completer = NuCompleter::new(Arc::new(engine_state.clone()), Arc::new(stack.clone()));
+    completer
+        .fetch_completions_at(line, byte_offset(line, cursor))
+        .into_iter()
+        .map(|s| Completion {
+            kind: s.kind.as_ref().map(kind_name),
+            value: s.suggestion.value,
+            description: s.suggestion.description,
+            start: char_offset(line, s.suggestion.span.start),
+            end: char_offset(line, s.suggestion.span.end),
+            append_whitespace: s.suggestion.append_whitespace,
+        })
+        .collect()
+}
+
+impl Command for HereticComplete {
+    fn name(&self) -> &str {
+        "heretic complete"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("line", SyntaxShape::String, "the current input line")
+            .required(
+                "cursor",
+                SyntaxShape::Int,
+                "cursor position within the line (in characters)",
+            )
+            .input_output_type(Type::Nothing, Type::table())
+            .category(Category::Misc)
+    }
+
+    fn description(&self) -> &str {
+        "get completion suggestions for a input line using nu's completer.\n\
+         spans are character offsets within the line and mark the text the suggestion replaces.\n\
+         this includes commands, flags, paths, variables, and custom/external completers (`$env.config.completions`).\n\
+         `kind` is one of command, value, cellpath, directory, file, flag, module, operator, variable, or null.\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let line: String = call.req(engine_state, stack, 0)?;
+        let cursor: Spanned<i64> = call.req(engine_state, stack, 1)?;
+        if cursor.item < 0 {
+            return Err(ShellError::IncorrectValue {
+                msg: "cursor can not be negative".into(),
+                val_span: cursor.span,
+                call_span: call.head,
+            });
+        }
+        let head = call.head;
+
+        Ok(PipelineData::Value(
+            Value::list(
+                completions(engine_state, stack, &line, cursor.item as usize)
+                    .into_iter()
+                    .map(|c| {
+                        Value::record(
+                            record! {
+                                "value" => Value::string(c.value, head),
+                                "description" => match c.description {
+                                    Some(d) => Value::string(d, head),
+                                    None => Value::nothing(head),
+                                },
+                                "kind" => match c.kind {
+                                    Some(k) => Value::string(k, head),
+                                    None => Value::nothing(head),
+                                },
+                                "span" => Value::record(record! {
+                                    "start" => Value::int(c.start as i64, head),
+                                    "end" => Value::int(c.end as i64, head),
+                                }, head),
+                                "append_whitespace" => Value::bool(c.append_whitespace, head),
+                            },
+                            head,
+                        )
+                    })
+                    .collect(),
+                head,
+            ),
+            None,
+        ))
+    }
+}
@@ -0,0 +1,43 @@
+use nu_engine::command_prelude::*;
+use nu_protocol::PipelineData;
+
+/// env var the REPL checks after every command (see `NuInstance::take_config_reload_request`)
+pub const CONFIG_RELOAD_REQUEST: &str = "_HERETIC_NU_CONFIG_RELOAD";
+
+#[derive(Clone)]
+pub struct HereticConfigReload;
+
+impl Command for HereticConfigReload {
+    fn name(&self) -> &str {
+        "heretic config reload"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .input_output_type(Type::Nothing, Type::Nothing)
+            .category(Category::Env)
+    }
+
+    fn description(&self) -> &str {
+        "reload the default config, the config files, and the autoload dirs once the current REPL command is done.\n\
+         definitions and env vars from the config replace the old ones, the rest of the session (cwd, variables, ..) stays.\n\
+         a reload only adds and replaces: definitions and env vars removed from a config stay until the shell is restarted.\n\
+         login.nu is not reloaded (it only runs when a login shell starts).\n\
+         if a config file fails, the error is reported and the previous config is kept.\n\
+         with `$env.heretic_nu_config_watch = true` this happens automatically when a config file or autoload dir changes.\n\
+         (that is no file-watcher: the modification times are compared before each prompt, so a change is picked up at the next prompt.)\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        _engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        stack.add_env_var(CONFIG_RELOAD_REQUEST.into(), Value::bool(true, call.head));
+        Ok(PipelineData::Value(Value::nothing(call.head), None))
+    }
+}
@@ -8,6 +8,16 @@ use crate::debug_x::{HereticDebuggerLogTarget, HereticDebuggerX};
 #[derive(Clone)]
 pub struct HereticDebug;
 
+fn debugger_error(error: &str, e: impl std::fmt::Display, call: &Call) -> ShellError {
+    ShellError::GenericError {
+        error: error.into(),
+        msg: e.to_string(),
+        span: Some(call.head),
+        help: None,
+        inner: vec![],
+    }
+}
+
 impl Command for HereticDebug {
     fn name(&self) -> &str {
         "heretic debug"
@@ -29,7 +39,10 @@ impl Command for HereticDebug {
                  file-path: ~/.local/share/heretic_nu/debug_logs/<timestamp>.txt",
                 None,
             )
-            .input_output_type(Type::Nothing, Type::Nothing)
+            .input_output_types(vec![
+                (Type::Nothing, Type::Nothing),
+                (Type::Nothing, Type::record()),
+            ])
             .category(Category::Debug)
     }
 
@@ -40,7 +53,7 @@ impl Command for HereticDebug {
     }
 
     fn extra_description(&self) -> &str {
-        ""
+        "'off' returns a report of the debugging session (event counts, errors, log-file, elapsed time)."
     }
 
     fn run(
@@ -62,7 +75,7 @@ impl Command for HereticDebug {
                     "file" => HereticDebuggerLogTarget::LogDir(
                         SystemTime::now()
                             .duration_since(UNIX_EPOCH)
-                            .expect("Time-Travel is not supported")
+                            .map_err(|e| debugger_error("Invalid system time", e, call))?
                             .as_secs(),
                     ),
                     _ => {
@@ -80,8 +93,9 @@ impl Command for HereticDebug {
                     .activate_debugger(Box::new(HereticDebuggerX {
                         log_target,
                         very_verbose: &val == "xx",
+                        ..HereticDebuggerX::default()
                     }))
-                    .expect("Failed to enable x-debugger");
+                    .map_err(|e| debugger_error("Failed to enable x-debugger", e, call))?;
             }
             "step" => {
                 if cfg!(feature = "heretic_step_debug") {
@@ -89,7 +103,7 @@ impl Command for HereticDebug {
                         .activate_debugger(Box::new(
                             crate::step_debug::HereticStepDebugger::default(),
                         ))
-                        .expect("Failed to enable step-debugger");
+                        .map_err(|e| debugger_error("Failed to enable step-debugger", e, call))?;
                 } else {
                     return Err(ShellError::IncorrectValue {
                         msg: "Heretic was compiled without 'heretic_step_debug' feature".into(),
@@ -99,9 +113,13 @@ impl Command for HereticDebug {
                 }
             }
             "off" => {
-                engine_state
+                let debugger = engine_state
                     .deactivate_debugger()
-                    .expect("Failed to disable debugger");
+                    .map_err(|e| debugger_error("Failed to disable debugger", e, call))?;
+                return Ok(PipelineData::Value(
+                    debugger.report(engine_state, call.span())?,
+                    None,
+                ));
             }
             _ => {
                 return Err(ShellError::IncorrectValue {
@@ -0,0 +1,133 @@
+use std::{fs::OpenOptions, io::Write, process::Command as ProcessCommand};
+
+use nu_engine::{command_prelude::*, env_to_strings};
+use nu_protocol::PipelineData;
+
+#[derive(Clone)]
+pub struct HereticEditBuffer;
+
+/// program and arguments of `$env.config.buffer_editor`, `$env.VISUAL`, or `$env.EDITOR`
+#[allow(clippy::result_large_err)]
+fn editor_command(
+    engine_state: &EngineState,
+    stack: &Stack,
+    span: Span,
+) -> Result<(String, Vec<String>), ShellError> {
+    let config = stack.get_config(engine_state);
+    let editor = [
+        Some(config.buffer_editor.clone()),
+        stack.get_env_var(engine_state, "VISUAL").cloned(),
+        stack.get_env_var(engine_state, "EDITOR").cloned(),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|value| match value {
+        Value::String { val, .. } => !val.is_empty(),
+        Value::List { vals, .. } => !vals.is_empty(),
+        _ => false,
+    });
+    match editor {
+        Some(Value::String { val, .. }) => Ok((val, vec![])),
+        Some(Value::List { vals, .. }) => {
+            let mut parts = vals
+                .iter()
+                .map(|part| part.coerce_string())
+                .collect::<Result<Vec<String>, ShellError>>()?;
+            let program = parts.remove(0);
+            Ok((program, parts))
+        }
+        _ => Err(ShellError::GenericError {
+            error: "No editor configured".into(),
+            msg: "`$env.config.buffer_editor`, `$env.VISUAL`, and `$env.EDITOR` are not set".into(),
+            span: Some(span),
+            help: Some("example: `$env.config.buffer_editor = 'vim'` or `$env.config.buffer_editor = ['code', '--wait']`".into()),
+            inner: vec![],
+        }),
+    }
+}
+
+/// edit `text` in the configured editor (via a temporary file) and return the result
+#[allow(clippy::result_large_err)]
+pub fn edit_buffer(
+    engine_state: &EngineState,
+    stack: &Stack,
+    text: &str,
+    span: Span,
+) -> Result<String, ShellError> {
+    let (program, args) = editor_command(engine_state, stack, span)?;
+    let io_error = |e: std::io::Error, msg: &str| ShellError::GenericError {
+        error: "Failed to edit the buffer".into(),
+        msg: format!("{msg}: {e}"),
+        span: Some(span),
+        help: None,
+        inner: vec![],
+    };
+
+    // a unpredictable name and `create_new` (which does not follow symlinks), so nobody can
+    // place a file or symlink there beforehand
+    let path = std::env::temp_dir().join(format!("heretic_nu_buffer_{}.nu", uuid::Uuid::new_v4()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|e| io_error(e, "could not write the temporary file"))?;
+
+    let mut command = ProcessCommand::new(&program);
+    command.args(&args).arg(&path);
+    command
+        .env_clear()
+        .envs(env_to_strings(engine_state, stack)?);
+    if let Ok(cwd) = engine_state.cwd(Some(stack)) {
+        command.current_dir(cwd.into_std_path_buf());
+    }
+    let status = command.status();
+    let res = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    let status = status.map_err(|e| io_error(e, &format!("could not run `{program}`")))?;
+    if !status.success() {
+        return Err(ShellError::GenericError {
+            error: "Failed to edit the buffer".into(),
+            msg: format!("`{program}` exited with {status}"),
+            span: Some(span),
+            help: None,
+            inner: vec![],
+        });
+    }
+    let res = res.map_err(|e| io_error(e, "could not read the temporary file"))?;
+    // editors like to end files with a newline
+    Ok(res.trim_end_matches(['\n', '\r']).to_string())
+}
+
+impl Command for HereticEditBuffer {
+    fn name(&self) -> &str {
+        "heretic edit-buffer"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .optional("text", SyntaxShape::String, "the initial content")
+            .input_output_type(Type::Nothing, Type::String)
+            .category(Category::Platform)
+    }
+
+    fn description(&self) -> &str {
+        "edit text in a external editor (`$env.config.buffer_editor`, `$env.VISUAL`, or `$env.EDITOR`) and return the result.\n\
+         used by the `open_editor` keybinding (`ctrl+o`) of the input functions.\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let text: Option<String> = call.opt(engine_state, stack, 0)?;
+        let res = edit_buffer(engine_state, stack, &text.unwrap_or_default(), call.head)?;
+        Ok(PipelineData::Value(Value::string(res, call.head), None))
+    }
+}
@@ -1,7 +1,7 @@
 use nu_engine::command_prelude::*;
 use nu_protocol::{debugger::WithoutDebug, engine::StateWorkingSet, PipelineData};
 
-use crate::NuInstance;
+use crate::{detached_parse_errors_to_shell_error, NuInstance};
 
 #[derive(Clone)]
 pub struct Evil;
@@ -41,6 +41,14 @@ impl Command for Evil {
         let mut working_set = StateWorkingSet::new(&engine_state);
         let mut block: std::sync::Arc<nu_protocol::ast::Block> =
             nu_parser::parse(&mut working_set, None, val.as_bytes(), false);
+        if !working_set.parse_errors.is_empty() {
+            // the spans would point into the discarded engine-state -> point at the argument instead
+            return Err(detached_parse_errors_to_shell_error(
+                &working_set.parse_errors,
+                &working_set,
+                val_r.span,
+            ));
+        }
         if block.ir_block.is_none() {
             let block_mut = std::sync::Arc::make_mut(&mut block);
             block_mut.ir_block = Some(match nu_engine::compile(&working_set, block_mut) {
@@ -77,7 +85,7 @@ impl ConstEvil {
     #[allow(clippy::result_large_err)]
     fn run_const_evil(call: &Call, code: String) -> Result<PipelineData, ShellError> {
         eprintln!("RUNNING EVAL CODE");
-        let mut ni = NuInstance::new().expect("Failed to create new nu instance");
+        let mut ni = NuInstance::new()?;
 
         let result = match ni.exec(&code, None) {
             Ok(v) => v,
@@ -107,7 +115,7 @@ impl ConstEvil {
             }
         };
         let result_nuon =
-            match nuon::to_nuon(&ni.engine_state, &result, nuon::ToStyle::Raw, None, false) {
+            match nuon::to_nuon(ni.engine_state(), &result, nuon::ToStyle::Raw, None, false) {
                 Ok(v) => v,
                 Err(e) => {
                     return Err(ShellError::NushellFailedSpanned {
@@ -0,0 +1,72 @@
+use nu_engine::command_prelude::*;
+use nu_protocol::PipelineData;
+
+use crate::history::SharedHistory;
+
+#[derive(Clone)]
+pub struct HereticHistoryCommand {
+    pub history: SharedHistory,
+}
+
+impl Command for HereticHistoryCommand {
+    fn name(&self) -> &str {
+        "heretic history"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .named(
+                "limit",
+                SyntaxShape::Int,
+                "only return the newest N entries",
+                Some('l'),
+            )
+            .input_output_type(Type::Nothing, Type::table())
+            .category(Category::History)
+    }
+
+    fn description(&self) -> &str {
+        "get the REPL history of all sessions (oldest first).\n\
+         the storage format is taken from `$env.config.history.file_format`.\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let limit = call.get_flag::<usize>(engine_state, stack, "limit")?;
+        let head = call.head;
+
+        let mut entries = self.history.entries(engine_state, stack)?;
+        if let Some(limit) = limit {
+            entries = entries.split_off(entries.len().saturating_sub(limit));
+        }
+
+        Ok(PipelineData::Value(
+            Value::list(
+                entries
+                    .into_iter()
+                    .map(|entry| {
+                        Value::record(
+                            record! {
+                                "start_timestamp" => Value::date(entry.start, head),
+                                "command" => Value::string(entry.command, head),
+                                "cwd" => Value::string(entry.cwd, head),
+                                "duration" => Value::duration(entry.duration.as_nanos() as i64, head),
+                                "exit_status" => Value::int(entry.exit_status, head),
+                            },
+                            head,
+                        )
+                    })
+                    .collect(),
+                head,
+            ),
+            None,
+        ))
+    }
+}
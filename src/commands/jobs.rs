@@ -0,0 +1,280 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use nu_engine::command_prelude::*;
+use nu_protocol::{
+    engine::{FrozenJob, Job},
+    PipelineData,
+};
+
+/// stopped jobs continued by `heretic bg` (job-id and pid).
+/// nu has no running state for them, so they stay listed as frozen in its job list.
+#[derive(Clone, Default)]
+pub struct BackgroundJobs(Arc<Mutex<HashSet<(usize, u32)>>>);
+
+impl BackgroundJobs {
+    fn lock(&self) -> MutexGuard<'_, HashSet<(usize, u32)>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// forget jobs, which stopped again, and remove the ones, which finished, from nu's job list
+    fn update(&self, engine_state: &EngineState) {
+        #[cfg(unix)]
+        self.lock().retain(|&(id, pid)| {
+            use crate::terminal::ProcessState;
+            match crate::terminal::poll_process(pid) {
+                ProcessState::Running => true,
+                ProcessState::Stopped => false,
+                ProcessState::Gone => {
+                    if let Ok(mut jobs) = engine_state.jobs.lock() {
+                        if frozen_jobs(&jobs).contains(&(id, pid)) {
+                            jobs.remove_job(nu_protocol::JobId::new(id));
+                        }
+                    }
+                    false
+                }
+            }
+        });
+        #[cfg(not(unix))]
+        let _ = engine_state;
+    }
+
+    fn contains(&self, id: usize, pid: u32) -> bool {
+        self.lock().contains(&(id, pid))
+    }
+}
+
+/// job-id and pid of nu's frozen jobs
+fn frozen_jobs(jobs: &nu_protocol::engine::Jobs) -> Vec<(usize, u32)> {
+    jobs.iter()
+        .filter_map(|(job_id, job)| match job {
+            Job::Frozen(FrozenJob { unfreeze, .. }) => Some((job_id.get(), unfreeze.pid())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// the frozen job with `id` (the newest one by default), skipping the jobs `skip` rejects
+#[allow(clippy::result_large_err)]
+fn find_frozen_job(
+    engine_state: &EngineState,
+    id: Option<usize>,
+    skip: impl Fn(usize, u32) -> bool,
+    head: Span,
+) -> Result<(usize, u32), ShellError> {
+    let frozen = frozen_jobs(
+        &engine_state
+            .jobs
+            .lock()
+            .map_err(|_| ShellError::GenericError {
+                error: "Failed to read the jobs".into(),
+                msg: "the job list is poisoned".into(),
+                span: Some(head),
+                help: None,
+                inner: vec![],
+            })?,
+    );
+    frozen
+        .into_iter()
+        .filter(|&(job_id, pid)| id.is_none_or(|id| id == job_id) && !skip(job_id, pid))
+        .max_by_key(|(job_id, _)| *job_id)
+        .ok_or_else(|| ShellError::GenericError {
+            error: match id {
+                None => "No stopped jobs".into(),
+                Some(id) => format!("No stopped job with id {id}"),
+            },
+            msg: "nothing to continue".into(),
+            span: Some(head),
+            help: Some("`heretic jobs` lists the jobs".into()),
+            inner: vec![],
+        })
+}
+
+/// run one of nu's `job` commands
+#[allow(clippy::result_large_err)]
+fn run_job_command(
+    engine_state: &EngineState,
+    stack: &mut Stack,
+    name: &str,
+    id: Option<usize>,
+    head: Span,
+) -> Result<PipelineData, ShellError> {
+    let decl_id = engine_state
+        .find_decl(name.as_bytes(), &[])
+        .ok_or_else(|| ShellError::GenericError {
+            error: "Command not found".into(),
+            msg: format!("`{name}` is not available on this platform"),
+            span: Some(head),
+            help: None,
+            inner: vec![],
+        })?;
+    let mut call = nu_protocol::ir::Call::build(decl_id, head);
+    if let Some(id) = id {
+        call.add_positional(stack, head, Value::int(id as i64, head));
+    }
+    call.with(stack, |stack, call| {
+        engine_state
+            .get_decl(decl_id)
+            .run(engine_state, stack, call, PipelineData::Empty)
+    })
+}
+
+#[derive(Clone)]
+pub struct HereticJobs {
+    pub background: BackgroundJobs,
+}
+
+impl Command for HereticJobs {
+    fn name(&self) -> &str {
+        "heretic jobs"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .input_output_type(Type::Nothing, Type::table())
+            .category(Category::Experimental)
+    }
+
+    fn description(&self) -> &str {
+        "list the jobs of this shell: background threads (`job spawn`), stopped (ctrl-z) external commands,\n\
+         and external commands continued with `heretic bg` (type `background`).\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let head = call.head;
+        self.background.update(engine_state);
+        let mut jobs =
+            run_job_command(engine_state, stack, "job list", None, head)?.into_value(head)?;
+        if let Value::List { vals, .. } = &mut jobs {
+            for job in vals {
+                let Value::Record { val, .. } = job else {
+                    continue;
+                };
+                let id = val.get("id").and_then(|id| id.as_int().ok());
+                let pid = match val.get("pids") {
+                    Some(Value::List { vals, .. }) => {
+                        vals.first().and_then(|pid| pid.as_int().ok())
+                    }
+                    _ => None,
+                };
+                if let (Some(id), Some(pid)) = (id, pid) {
+                    if self.background.contains(id as usize, pid as u32) {
+                        val.to_mut()
+                            .insert("type", Value::string("background", head));
+                    }
+                }
+            }
+        }
+        Ok(PipelineData::Value(jobs, None))
+    }
+}
+
+#[derive(Clone)]
+pub struct HereticFg {
+    pub background: BackgroundJobs,
+}
+
+impl Command for HereticFg {
+    fn name(&self) -> &str {
+        "heretic fg"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .optional(
+                "id",
+                SyntaxShape::Int,
+                "the job id (default: the newest stopped or background job)",
+            )
+            .input_output_type(Type::Nothing, Type::Any)
+            .category(Category::Experimental)
+    }
+
+    fn description(&self) -> &str {
+        "continue a stopped (ctrl-z) or background job in the foreground (the newest one by default).\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let id: Option<usize> = call.opt(engine_state, stack, 0)?;
+        self.background.update(engine_state);
+        let (id, pid) = find_frozen_job(engine_state, id, |_, _| false, call.head)?;
+        // if it is stopped again it is listed as frozen
+        self.background.lock().remove(&(id, pid));
+        run_job_command(engine_state, stack, "job unfreeze", Some(id), call.head)
+    }
+}
+
+#[cfg(unix)]
+#[derive(Clone)]
+pub struct HereticBg {
+    pub background: BackgroundJobs,
+}
+
+#[cfg(unix)]
+impl Command for HereticBg {
+    fn name(&self) -> &str {
+        "heretic bg"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .optional(
+                "id",
+                SyntaxShape::Int,
+                "the job id (default: the newest stopped job)",
+            )
+            .input_output_type(Type::Nothing, Type::Nothing)
+            .category(Category::Experimental)
+    }
+
+    fn description(&self) -> &str {
+        "continue a stopped (ctrl-z) job in the background (the newest one by default).\n\
+         `heretic jobs` lists it as `background` until it finishes, `heretic fg` brings it to the foreground.\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let id: Option<usize> = call.opt(engine_state, stack, 0)?;
+        self.background.update(engine_state);
+        let (id, pid) = find_frozen_job(
+            engine_state,
+            id,
+            |id, pid| self.background.contains(id, pid),
+            call.head,
+        )?;
+        crate::terminal::continue_process_group(pid).map_err(|e| ShellError::GenericError {
+            error: "Failed to continue the job".into(),
+            msg: e.to_string(),
+            span: Some(call.head),
+            help: None,
+            inner: vec![],
+        })?;
+        self.background.lock().insert((id, pid));
+        Ok(PipelineData::Value(Value::nothing(call.head), None))
+    }
+}
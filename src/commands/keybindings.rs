@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+
+use nu_engine::command_prelude::*;
+use nu_protocol::PipelineData;
+
+use crate::line_editor::{default_keybindings, key_name_from_value, normalize_key_name, Action};
+
+#[derive(Clone)]
+pub struct HereticKeybindings;
+
+#[derive(Clone)]
+pub struct HereticKeyName;
+
+/// the default keybindings merged with `$env.heretic_nu_keybindings`
+/// (key name -> action name or closure, `null` removes a default binding)
+#[allow(clippy::result_large_err)]
+pub fn keybindings(
+    engine_state: &EngineState,
+    stack: &Stack,
+    span: Span,
+) -> Result<BTreeMap<String, Value>, ShellError> {
+    let mut res: BTreeMap<String, Value> = default_keybindings()
+        .into_iter()
+        .map(|(key, action)| (key, Value::string(action.name(), span)))
+        .collect();
+
+    let user = match stack.get_env_var(engine_state, "heretic_nu_keybindings") {
+        None | Some(Value::Nothing { .. }) => return Ok(res),
+        Some(Value::Record { val, .. }) => val,
+        Some(other) => {
+            return Err(ShellError::GenericError {
+                error: "Invalid keybindings".into(),
+                msg: format!("expected a record, got {}", other.get_type()),
+                span: Some(other.span()),
+                help: Some("`$env.heretic_nu_keybindings` maps key names (`ctrl+a`) to editor actions or closures".into()),
+                inner: vec![],
+            });
+        }
+    };
+    for (key, value) in user.iter() {
+        let Some(key) = normalize_key_name(key) else {
+            return Err(ShellError::GenericError {
+                error: "Invalid keybinding".into(),
+                msg: format!("unknown modifier in `{key}`"),
+                span: Some(value.span()),
+                help: Some(
+                    "known modifiers are ctrl, alt, and shift (example: `ctrl+alt+a`)".into(),
+                ),
+                inner: vec![],
+            });
+        };
+        match value {
+            Value::Nothing { .. } => {
+                res.remove(&key);
+            }
+            Value::Closure { .. } => {
+                res.insert(key, value.clone());
+            }
+            Value::String { val, .. } if Action::from_name(val).is_some() => {
+                res.insert(key, value.clone());
+            }
+            Value::String { val, .. } => {
+                return Err(ShellError::GenericError {
+                    error: "Invalid keybinding".into(),
+                    msg: format!("unknown editor action: {val}"),
+                    span: Some(value.span()),
+                    help: Some(format!(
+                        "known actions: {}",
+                        Action::names().collect::<Vec<_>>().join(", ")
+                    )),
+                    inner: vec![],
+                });
+            }
+            other => {
+                return Err(ShellError::GenericError {
+                    error: "Invalid keybinding".into(),
+                    msg: format!(
+                        "expected an action name, closure, or null, got {}",
+                        other.get_type()
+                    ),
+                    span: Some(other.span()),
+                    help: None,
+                    inner: vec![],
+                });
+            }
+        }
+    }
+    Ok(res)
+}
+
+impl Command for HereticKeybindings {
+    fn name(&self) -> &str {
+        "heretic keybindings"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .input_output_type(Type::Nothing, Type::record())
+            .category(Category::Platform)
+    }
+
+    fn description(&self) -> &str {
+        "get the keybindings used by the input functions (default bindings merged with `$env.heretic_nu_keybindings`).\n\
+         keys are key names like `ctrl+a`, `alt+backspace`, `shift+tab`, `enter`, or `A`.\n\
+         values are editor action names or closures.\n\
+         a closure gets `{text, cursor}` and returns `null`, a action name, or a record with the optional keys `text`, `cursor`, and `action`.\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let head = call.head;
+        let record: Record = keybindings(engine_state, stack, head)?
+            .into_iter()
+            .collect();
+        Ok(PipelineData::Value(Value::record(record, head), None))
+    }
+}
+
+impl Command for HereticKeyName {
+    fn name(&self) -> &str {
+        "heretic key name"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .input_output_types(vec![
+                (Type::record(), Type::Any),
+                (Type::String, Type::String),
+            ])
+            .category(Category::Platform)
+    }
+
+    fn description(&self) -> &str {
+        "get the key name (as used by `heretic keybindings`) of a `input listen` event or normalise a written key name.\n\
+         returns null for events without a key name (non-key events, media-keys, ..).\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        _engine_state: &EngineState,
+        _stack: &mut Stack,
+        call: &Call,
+        input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let head = call.head;
+        let value = input.into_value(head)?;
+        let name = match &value {
+            Value::String { val, .. } => {
+                Some(
+                    normalize_key_name(val).ok_or_else(|| ShellError::IncorrectValue {
+                        msg: "unknown modifier (known: ctrl, alt, shift)".into(),
+                        val_span: value.span(),
+                        call_span: head,
+                    })?,
+                )
+            }
+            other => key_name_from_value(other),
+        };
+        Ok(PipelineData::Value(
+            match name {
+                Some(name) => Value::string(name, head),
+                None => Value::nothing(head),
+            },
+            None,
+        ))
+    }
+}
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, sync::Arc};
+
+use nu_cli::NuHighlighter;
+use nu_engine::{command_prelude::*, ClosureEvalOnce};
+use nu_protocol::{engine::Closure, PipelineData};
+use reedline::Highlighter;
+
+use crate::{
+    commands::{complete, edit_buffer, keybindings, parse_check},
+    history::SharedHistory,
+    line_editor::{Action, Binding, CustomEdit, EditorHost, LineEditor, Outcome, PromptInfo},
+};
+
+#[derive(Clone)]
+pub struct HereticLineEditor {
+    pub history: SharedHistory,
+}
+
+struct Host<'a> {
+    engine_state: &'a EngineState,
+    stack: &'a mut Stack,
+    call: &'a Call<'a>,
+    /// built once per line, not per keypress (it holds a copy of the engine-state)
+    highlighter: NuHighlighter,
+    /// closure keybindings by key name
+    closures: HashMap<String, Closure>,
+}
+
+impl EditorHost for Host<'_> {
+    fn highlight(&mut self, text: &str) -> String {
+        self.highlighter.highlight(text, 0).render_simple()
+    }
+
+    fn complete(&mut self, text: &str, cursor: usize) -> Vec<complete::Completion> {
+        complete::completions(self.engine_state, self.stack, text, cursor)
+    }
+
+    fn is_complete(&mut self, text: &str) -> bool {
+        parse_check::is_complete(self.engine_state, text)
+    }
+
+    fn custom(&mut self, key: &str, text: &str, cursor: usize) -> Result<CustomEdit, String> {
+        let Some(closure) = self.closures.get(key) else {
+            return Ok(CustomEdit::default());
+        };
+        let head = self.call.head;
+        let state = Value::record(
+            record! {
+                "text" => Value::string(text, head),
+                "cursor" => Value::int(cursor as i64, head),
+            },
+            head,
+        );
+        let value = ClosureEvalOnce::new(self.engine_state, self.stack, closure.clone())
+            .run_with_value(state)
+            .and_then(|pd| pd.into_value(head))
+            .map_err(|e| e.to_string())?;
+        CustomEdit::from_value(&value)
+    }
+
+    fn open_editor(&mut self, text: &str) -> Result<String, String> {
+        edit_buffer::edit_buffer(self.engine_state, self.stack, text, self.call.head)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Command for HereticLineEditor {
+    fn name(&self) -> &str {
+        "heretic line-editor"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .input_output_types(vec![
+                (Type::Nothing, Type::String),
+                (Type::Nothing, Type::Nothing),
+            ])
+            .category(Category::Platform)
+    }
+
+    fn description(&self) -> &str {
+        "read a line using the builtin line-editor (a faster alternative to the nu-scripted input).\n\
+         enable it with `$env.heretic_nu_line_editor = 'native'`.\n\
+         it has cursor-movement, word-motions, kill-ring, undo, history, highlighting, and a completion-menu.\n\
+         `$env.config.edit_mode = 'vi'` enables vi mode (normal/insert/visual, operators, motions, counts).\n\
+         returns nothing if the user wants to exit (ctrl-d on a empty line).\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let history = self
+            .history
+            .entries(engine_state, stack)
+            .map(|entries| entries.into_iter().map(|entry| entry.command).collect())
+            .unwrap_or_default();
+        let prompt = PromptInfo::from_value(stack.get_env_var(engine_state, "_HERETIC_NU_PROMPT"));
+
+        let mut bindings = HashMap::new();
+        let mut closures = HashMap::new();
+        for (key, value) in keybindings::keybindings(engine_state, stack, call.head)? {
+            match value {
+                Value::Closure { val, .. } => {
+                    bindings.insert(key.clone(), Binding::Custom(key.clone()));
+                    closures.insert(key, *val);
+                }
+                Value::String { val, .. } => {
+                    if let Some(action) = Action::from_name(&val) {
+                        bindings.insert(key, Binding::Action(action));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let highlighter = NuHighlighter {
+            engine_state: Arc::new(engine_state.clone()),
+            stack: Arc::new(stack.clone()),
+        };
+        let mut editor = LineEditor::new(prompt, bindings, history);
+        let outcome = editor
+            .read_line(&mut Host {
+                engine_state,
+                stack,
+                call,
+                highlighter,
+                closures,
+            })
+            .map_err(|e| ShellError::GenericError {
+                error: "Line-editor failed".into(),
+                msg: e.to_string(),
+                span: Some(call.head),
+                help: None,
+                inner: vec![],
+            })?;
+
+        match outcome {
+            Outcome::Submit(text) => Ok(PipelineData::Value(Value::string(text, call.head), None)),
+            Outcome::Cancel => Ok(PipelineData::Value(Value::string("", call.head), None)),
+            Outcome::Exit => Ok(PipelineData::Value(Value::nothing(call.head), None)),
+        }
+    }
+}
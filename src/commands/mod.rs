@@ -1,6 +1,15 @@
+pub mod complete;
+pub mod config_reload;
 pub mod debug;
+pub mod edit_buffer;
 pub mod evil;
 pub mod here_span;
+pub mod history;
+pub mod jobs;
+pub mod keybindings;
+pub mod line_editor;
+pub mod parse_check;
+pub mod prompt;
 #[cfg(feature = "heretic_test")]
 pub mod run_tests;
 pub mod version;
@@ -0,0 +1,92 @@
+use nu_engine::command_prelude::*;
+use nu_protocol::{ParseError, PipelineData};
+
+#[derive(Clone)]
+pub struct HereticParseCheck;
+
+fn is_incomplete_error(error: &ParseError) -> bool {
+    matches!(
+        error,
+        ParseError::UnexpectedEof(..) | ParseError::Unclosed(..)
+    )
+}
+
+/// false if the code just ended too early (unclosed `{`, string, ..)
+pub fn is_complete(engine_state: &EngineState, code: &str) -> bool {
+    let mut working_set = StateWorkingSet::new(engine_state);
+    let _ = nu_parser::parse(&mut working_set, None, code.as_bytes(), false);
+    !working_set.parse_errors.iter().any(is_incomplete_error)
+}
+
+impl Command for HereticParseCheck {
+    fn name(&self) -> &str {
+        "heretic parse check"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("code", SyntaxShape::String, "the code to check")
+            .input_output_type(Type::Nothing, Type::record())
+            .category(Category::Misc)
+    }
+
+    fn description(&self) -> &str {
+        "parse code without running it.\n\
+         `complete` is false if the code just ended too early (unclosed `{`, `[`, `(`, string, ..),\n\
+         `valid` is false if there are any parse errors.\n\
+         spans are character offsets within the code.\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let code: String = call.req(engine_state, stack, 0)?;
+        let head = call.head;
+
+        let mut working_set = StateWorkingSet::new(engine_state);
+        let offset = working_set.next_span_start();
+        let _ = nu_parser::parse(&mut working_set, None, code.as_bytes(), false);
+
+        let complete = !working_set.parse_errors.iter().any(is_incomplete_error);
+        let char_offset = |pos: usize| -> i64 {
+            code.char_indices()
+                .take_while(|(i, _)| *i < pos.saturating_sub(offset))
+                .count() as i64
+        };
+        let errors: Vec<Value> = working_set
+            .parse_errors
+            .iter()
+            .map(|e| {
+                let span = e.span();
+                Value::record(
+                    record! {
+                        "msg" => Value::string(e.to_string(), head),
+                        "span" => Value::record(record! {
+                            "start" => Value::int(char_offset(span.start), head),
+                            "end" => Value::int(char_offset(span.end), head),
+                        }, head),
+                    },
+                    head,
+                )
+            })
+            .collect();
+
+        Ok(PipelineData::Value(
+            Value::record(
+                record! {
+                    "complete" => Value::bool(complete, head),
+                    "valid" => Value::bool(errors.is_empty(), head),
+                    "errors" => Value::list(errors, head),
+                },
+                head,
+            ),
+            None,
+        ))
+    }
+}
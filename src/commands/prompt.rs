@@ -0,0 +1,53 @@
+use nu_engine::command_prelude::*;
+use nu_protocol::PipelineData;
+
+use crate::ansi;
+
+#[derive(Clone)]
+pub struct HereticPromptMeasure;
+
+impl Command for HereticPromptMeasure {
+    fn name(&self) -> &str {
+        "heretic prompt measure"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("text", SyntaxShape::String, "the (ansi-formatted) text")
+            .input_output_type(Type::Nothing, Type::record())
+            .category(Category::Platform)
+    }
+
+    fn description(&self) -> &str {
+        "measure how a text would be displayed in the terminal (ansi escapes stripped).\n\
+         `lines`: rows it takes up (including wrapping), `width`: cells in the last row,\n\
+         `columns`: width of the terminal.\n\
+         \n\
+         PART OF HERETIC-NU"
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        _input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let text: String = call.req(engine_state, stack, 0)?;
+        let head = call.head;
+        let columns = ansi::terminal_columns();
+        let (lines, width) = ansi::measure(&text, columns);
+
+        Ok(PipelineData::Value(
+            Value::record(
+                record! {
+                    "lines" => Value::int(lines as i64, head),
+                    "width" => Value::int(width as i64, head),
+                    "columns" => Value::int(columns as i64, head),
+                },
+                head,
+            ),
+            None,
+        ))
+    }
+}
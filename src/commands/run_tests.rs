@@ -112,13 +112,11 @@ fn for_decl(
 
     if is_test {
         if parameterizibla_values.is_empty() {
-            let mut tni = NuInstance {
-                engine_state: engine_state.clone(),
-                stack: stack.clone(),
-            };
+            let mut tni = NuInstance::from_parts(engine_state.clone(), stack.clone());
+            let (tni_engine_state, tni_stack) = tni.parts_mut();
             nu_engine::eval_call::<WithoutDebug>(
-                &tni.engine_state,
-                &mut tni.stack,
+                tni_engine_state,
+                tni_stack,
                 &ast::Call {
                     decl_id,
                     head: span,
@@ -129,10 +127,7 @@ fn for_decl(
             )?;
         } else {
             for param_combination in CartesianProduct::new(parameterizibla_values) {
-                let mut tni = NuInstance {
-                    engine_state: engine_state.clone(),
-                    stack: stack.clone(),
-                };
+                let mut tni = NuInstance::from_parts(engine_state.clone(), stack.clone());
                 let mut arguments = Vec::new();
                 for (param_idx, param_value) in param_combination.into_iter().enumerate() {
                     // dbg!((&param_idx, &param_value));
@@ -149,9 +144,10 @@ fn for_decl(
                         Some(ast::Expression::new_unknown(ast::Expr::Var(vid), span, vt)),
                     )));
                 }
+                let (tni_engine_state, tni_stack) = tni.parts_mut();
                 nu_engine::eval_call::<WithoutDebug>(
-                    &tni.engine_state,
-                    &mut tni.stack,
+                    tni_engine_state,
+                    tni_stack,
                     &ast::Call {
                         decl_id,
                         head: span,
@@ -1,19 +1,36 @@
-use std::{fs::OpenOptions, io::Write, path::PathBuf};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use nu_protocol::debugger::Debugger;
+use nu_protocol::{debugger::Debugger, record, ShellError, Span, Value};
 
 type LogIdType = u64;
 
 const CODE_PREVIEW_LENGTH: usize = 20;
 
-fn log_file(id: LogIdType) -> PathBuf {
-    std::env::home_dir()
-        .expect("No home dir")
-        .join(".local")
-        .join("share")
-        .join("heretic_nu")
-        .join("debug_logs")
-        .join(format!("{id}.txt"))
+pub fn log_file(id: LogIdType) -> PathBuf {
+    crate::dirs::debug_log_dir().join(format!("{id}.txt"))
+}
+
+/// id of a new log-file (the current unix time)
+pub fn new_log_id() -> LogIdType {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
+/// create (or empty) log-file `id` and its directory
+pub fn create_log_file(id: LogIdType) -> std::io::Result<PathBuf> {
+    let path = log_file(id);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::File::create(&path)?;
+    Ok(path)
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -29,6 +46,13 @@ impl Default for HereticDebuggerLogTarget {
     }
 }
 impl HereticDebuggerLogTarget {
+    pub fn file(&self) -> Option<PathBuf> {
+        match self {
+            HereticDebuggerLogTarget::StdErr | HereticDebuggerLogTarget::StdOut => None,
+            HereticDebuggerLogTarget::LogDir(id) => Some(log_file(*id)),
+        }
+    }
+
     pub fn log(&self, message: &str) {
         match self {
             HereticDebuggerLogTarget::StdErr => eprintln!("-x- {message}"),
@@ -46,10 +70,122 @@ impl HereticDebuggerLogTarget {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+/// Bookkeeping shared by the heretic debuggers so `report` has something to say.
+#[derive(Clone, Debug, Default)]
+pub struct DebugSession {
+    started: Option<Instant>,
+    stopped: Option<Instant>,
+    enter_block: u64,
+    leave_block: u64,
+    enter_element: u64,
+    leave_element: u64,
+    enter_instruction: u64,
+    leave_instruction: u64,
+    errors: Vec<(String, Span)>,
+}
+
+impl DebugSession {
+    pub fn start(&mut self) {
+        *self = Self {
+            started: Some(Instant::now()),
+            ..Self::default()
+        };
+    }
+
+    pub fn stop(&mut self) {
+        self.stopped = Some(Instant::now());
+    }
+
+    pub fn enter_block(&mut self) {
+        self.enter_block += 1;
+    }
+    pub fn leave_block(&mut self) {
+        self.leave_block += 1;
+    }
+    pub fn enter_element(&mut self) {
+        self.enter_element += 1;
+    }
+    pub fn leave_element(&mut self) {
+        self.leave_element += 1;
+    }
+    pub fn enter_instruction(&mut self) {
+        self.enter_instruction += 1;
+    }
+    pub fn leave_instruction(&mut self) {
+        self.leave_instruction += 1;
+    }
+
+    pub fn error(&mut self, error: &ShellError, span: Span) {
+        let msg = error.to_string();
+        // an error bubbling up leaves every instruction on its way with the same span
+        if self.errors.last() != Some(&(msg.clone(), span)) {
+            self.errors.push((msg, span));
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        match (self.started, self.stopped) {
+            (Some(started), Some(stopped)) => stopped.duration_since(started),
+            (Some(started), None) => started.elapsed(),
+            (None, _) => Duration::ZERO,
+        }
+    }
+
+    pub fn report(
+        &self,
+        engine_state: &nu_protocol::engine::EngineState,
+        debugger: &str,
+        log_file: Option<PathBuf>,
+        span: Span,
+    ) -> Value {
+        let errors = self
+            .errors
+            .iter()
+            .map(|(msg, error_span)| {
+                Value::record(
+                    record! {
+                        "msg" => Value::string(msg, span),
+                        "span" => Value::record(record! {
+                            "start" => Value::int(error_span.start as i64, span),
+                            "end" => Value::int(error_span.end as i64, span),
+                        }, span),
+                        "code" => Value::string(
+                            String::from_utf8_lossy(engine_state.get_span_contents(*error_span)),
+                            span,
+                        ),
+                    },
+                    span,
+                )
+            })
+            .collect();
+        Value::record(
+            record! {
+                "debugger" => Value::string(debugger, span),
+                "events" => Value::record(record! {
+                    "enter_block" => Value::int(self.enter_block as i64, span),
+                    "leave_block" => Value::int(self.leave_block as i64, span),
+                    "enter_element" => Value::int(self.enter_element as i64, span),
+                    "leave_element" => Value::int(self.leave_element as i64, span),
+                    "enter_instruction" => Value::int(self.enter_instruction as i64, span),
+                    "leave_instruction" => Value::int(self.leave_instruction as i64, span),
+                }, span),
+                "errors" => Value::list(errors, span),
+                "log_file" => match log_file {
+                    Some(path) => Value::string(path.to_string_lossy(), span),
+                    None => Value::nothing(span),
+                },
+                "elapsed" => Value::duration(self.elapsed().as_nanos() as i64, span),
+            },
+            span,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct HereticDebuggerX {
     pub log_target: HereticDebuggerLogTarget,
     pub very_verbose: bool,
+    pub session: DebugSession,
 }
 
 impl HereticDebuggerX {
@@ -113,19 +249,14 @@ impl Debugger for HereticDebuggerX {
             HereticDebuggerLogTarget::StdErr => (),
             HereticDebuggerLogTarget::StdOut => (),
             HereticDebuggerLogTarget::LogDir(id) => {
-                let lf = log_file(id);
-                let lfp = lf
-                    .parent()
-                    .expect("failed to get parent-dir of log-file (impossible)");
-                if !std::fs::exists(lfp).expect("Failed to check if log-dir exists") {
-                    std::fs::create_dir_all(lfp).expect("Failed to create log-dir");
-                }
-                std::fs::File::create(lf).expect("Failed to create log-file");
+                create_log_file(id).expect("Failed to create log-file");
             }
         }
+        self.session.start();
         self.log("activated HereticDebuggerX")
     }
     fn deactivate(&mut self) {
+        self.session.stop();
         self.log("deactivated HereticDebuggerX");
     }
 
@@ -134,6 +265,7 @@ impl Debugger for HereticDebuggerX {
         engine_state: &nu_protocol::engine::EngineState,
         block: &nu_protocol::ast::Block,
     ) {
+        self.session.enter_block();
         self.for_block(engine_state, block, "enter block");
     }
 
@@ -142,6 +274,7 @@ impl Debugger for HereticDebuggerX {
         engine_state: &nu_protocol::engine::EngineState,
         block: &nu_protocol::ast::Block,
     ) {
+        self.session.leave_block();
         self.for_block(engine_state, block, "leave block");
     }
 
@@ -150,6 +283,7 @@ impl Debugger for HereticDebuggerX {
         engine_state: &nu_protocol::engine::EngineState,
         pipeline_element: &nu_protocol::ast::PipelineElement,
     ) {
+        self.session.enter_element();
         self.for_element(engine_state, pipeline_element, "enter element");
     }
 
@@ -159,6 +293,10 @@ impl Debugger for HereticDebuggerX {
         element: &nu_protocol::ast::PipelineElement,
         result: &Result<nu_protocol::PipelineData, nu_protocol::ShellError>,
     ) {
+        self.session.leave_element();
+        if let Err(err) = result {
+            self.session.error(err, element.expr.span);
+        }
         self.for_element(
             engine_state,
             element,
@@ -177,6 +315,7 @@ impl Debugger for HereticDebuggerX {
         instruction_index: usize,
         _registers: &[nu_protocol::PipelineExecutionData],
     ) {
+        self.session.enter_instruction();
         self.for_instruction(
             engine_state,
             ir_block,
@@ -193,6 +332,10 @@ impl Debugger for HereticDebuggerX {
         _registers: &[nu_protocol::PipelineExecutionData],
         error: Option<&nu_protocol::ShellError>,
     ) {
+        self.session.leave_instruction();
+        if let Some(err) = error {
+            self.session.error(err, ir_block.spans[instruction_index]);
+        }
         self.for_instruction(
             engine_state,
             ir_block,
@@ -207,9 +350,69 @@ impl Debugger for HereticDebuggerX {
 
     fn report(
         &self,
-        _engine_state: &nu_protocol::engine::EngineState,
+        engine_state: &nu_protocol::engine::EngineState,
         debugger_span: nu_protocol::Span,
     ) -> Result<nu_protocol::Value, nu_protocol::ShellError> {
-        Ok(nu_protocol::Value::nothing(debugger_span))
+        Ok(self.session.report(
+            engine_state,
+            if self.very_verbose { "xx" } else { "x" },
+            self.log_target.file(),
+            debugger_span,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NuInstance;
+
+    #[test]
+    fn report_of_x_debugger() {
+        let mut ni = NuInstance::new().unwrap();
+        ni.engine_state()
+            .activate_debugger(Box::new(HereticDebuggerX {
+                log_target: HereticDebuggerLogTarget::StdErr,
+                ..HereticDebuggerX::default()
+            }))
+            .unwrap();
+        ni.exec("1 + 2", None).unwrap();
+        assert!(ni.exec("error make {msg: boom}", None).is_err());
+        let debugger = ni.engine_state().deactivate_debugger().unwrap();
+        let report = debugger
+            .report(ni.engine_state(), Span::test_data())
+            .unwrap();
+
+        let report = report.as_record().unwrap();
+        assert_eq!(report.get("debugger"), Some(&Value::test_string("x")));
+        assert_eq!(report.get("log_file"), Some(&Value::test_nothing()));
+        let events = report.get("events").unwrap().as_record().unwrap();
+        assert!(events.get("enter_block").unwrap().as_int().unwrap() > 0);
+        let errors = report.get("errors").unwrap().as_list().unwrap();
+        assert!(!errors.is_empty());
+        assert!(errors[0]
+            .as_record()
+            .unwrap()
+            .get("msg")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .contains("boom"));
+    }
+
+    #[test]
+    fn report_log_file() {
+        let debugger = HereticDebuggerX {
+            log_target: HereticDebuggerLogTarget::LogDir(42),
+            ..HereticDebuggerX::default()
+        };
+        let ni = NuInstance::new().unwrap();
+        let report = debugger
+            .report(ni.engine_state(), Span::test_data())
+            .unwrap();
+        assert_eq!(
+            report.as_record().unwrap().get("log_file"),
+            Some(&Value::test_string(log_file(42).to_string_lossy()))
+        );
     }
 }
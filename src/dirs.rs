@@ -0,0 +1,83 @@
+//! where heretic_nu keeps its files:
+//! `$HERETIC_NU_<KIND>_DIR`, then `$XDG_<KIND>_HOME/heretic_nu`, then the XDG default below the
+//! home directory. `None` if none of them is available (containers without `HOME`).
+
+use std::path::PathBuf;
+
+use nu_protocol::{record, Span, Value};
+
+/// a absolute path from a environment variable (relative ones are ignored, like XDG says)
+fn env_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+fn dir(override_var: &str, xdg_var: &str, home_default: &[&str]) -> Option<PathBuf> {
+    if let Some(dir) = env_path(override_var) {
+        return Some(dir);
+    }
+    if let Some(dir) = env_path(xdg_var) {
+        return Some(dir.join("heretic_nu"));
+    }
+    let mut dir = std::env::home_dir()?;
+    dir.extend(home_default);
+    Some(dir.join("heretic_nu"))
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    dir("HERETIC_NU_CONFIG_DIR", "XDG_CONFIG_HOME", &[".config"])
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    dir("HERETIC_NU_DATA_DIR", "XDG_DATA_HOME", &[".local", "share"])
+}
+
+pub fn state_dir() -> Option<PathBuf> {
+    dir(
+        "HERETIC_NU_STATE_DIR",
+        "XDG_STATE_HOME",
+        &[".local", "state"],
+    )
+}
+
+pub fn config_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.nu"))
+}
+
+/// only loaded by login shells
+pub fn login_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("login.nu"))
+}
+
+/// the plugin registry file (written by `plugin add`)
+pub fn plugin_file() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("plugin.msgpackz"))
+}
+
+/// falls back to the temp-dir, so the debugger always has somewhere to log to
+pub fn debug_log_dir() -> PathBuf {
+    data_dir()
+        .unwrap_or_else(|| std::env::temp_dir().join("heretic_nu"))
+        .join("debug_logs")
+}
+
+/// the value of the `$heretic` constant
+pub fn heretic_constant(span: Span) -> Value {
+    let path = |path: Option<PathBuf>| match path {
+        Some(path) => Value::string(path.to_string_lossy(), span),
+        None => Value::nothing(span),
+    };
+    Value::record(
+        record! {
+            "config-dir" => path(config_dir()),
+            "config-path" => path(config_file()),
+            "login-path" => path(login_file()),
+            "data-dir" => path(data_dir()),
+            "state-dir" => path(state_dir()),
+            "debug-log-dir" => path(Some(debug_log_dir())),
+        },
+        span,
+    )
+}
@@ -0,0 +1,158 @@
+//! `NuInstance::register_fn`: rust closures as nu commands (without a `Command` impl).
+//!
+//! ```
+//! use nu_protocol::{ShellError, Signature, SyntaxShape, Type, Value};
+//!
+//! let mut ni = heretic_nu::NuInstance::new()?;
+//! ni.register_fn(
+//!     "greet",
+//!     Signature::build("greet")
+//!         .required("name", SyntaxShape::String, "who to greet")
+//!         .switch("loud", "shout it", Some('l'))
+//!         .input_output_type(Type::Nothing, Type::String),
+//!     |args, _input| {
+//!         let name: String = args.req(0)?;
+//!         let greeting = format!("hello {name}");
+//!         Ok(Value::string(
+//!             if args.has_flag("loud")? { greeting.to_uppercase() } else { greeting },
+//!             args.head(),
+//!         ))
+//!     },
+//! )?;
+//! assert_eq!(ni.eval_to_string("greet --loud world")?, "HELLO WORLD");
+//! # Ok::<(), ShellError>(())
+//! ```
+
+use std::sync::Arc;
+
+use nu_engine::command_prelude::*;
+use nu_protocol::{FromValue, PipelineData};
+
+use crate::NuInstance;
+
+type Callback = dyn Fn(&mut FnArgs, PipelineData) -> Result<Value, ShellError> + Send + Sync;
+
+/// the arguments of a `register_fn` call
+pub struct FnArgs<'a> {
+    engine_state: &'a EngineState,
+    stack: &'a mut Stack,
+    call: &'a Call<'a>,
+}
+
+impl FnArgs<'_> {
+    /// span of the command name (for returned values and errors)
+    pub fn head(&self) -> Span {
+        self.call.head
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn req<T: FromValue>(&mut self, pos: usize) -> Result<T, ShellError> {
+        self.call.req(self.engine_state, self.stack, pos)
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn opt<T: FromValue>(&mut self, pos: usize) -> Result<Option<T>, ShellError> {
+        self.call.opt(self.engine_state, self.stack, pos)
+    }
+
+    /// the rest-arguments starting at `start`
+    #[allow(clippy::result_large_err)]
+    pub fn rest<T: FromValue>(&mut self, start: usize) -> Result<Vec<T>, ShellError> {
+        self.call.rest(self.engine_state, self.stack, start)
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn get_flag<T: FromValue>(&mut self, name: &str) -> Result<Option<T>, ShellError> {
+        self.call.get_flag(self.engine_state, self.stack, name)
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn has_flag(&mut self, name: &str) -> Result<bool, ShellError> {
+        self.call.has_flag(self.engine_state, self.stack, name)
+    }
+
+    pub fn engine_state(&self) -> &EngineState {
+        self.engine_state
+    }
+
+    /// the caller's stack (env changes stay after the command, like with `def --env`)
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        self.stack
+    }
+}
+
+/// a command running a rust closure, see `NuInstance::register_fn`
+#[derive(Clone)]
+pub struct FnCommand {
+    signature: Signature,
+    callback: Arc<Callback>,
+}
+
+impl FnCommand {
+    /// the name of the command is taken from the signature
+    pub fn new(
+        signature: Signature,
+        callback: impl Fn(&mut FnArgs, PipelineData) -> Result<Value, ShellError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self {
+            signature,
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+impl Command for FnCommand {
+    fn name(&self) -> &str {
+        &self.signature.name
+    }
+
+    fn signature(&self) -> Signature {
+        self.signature.clone()
+    }
+
+    fn description(&self) -> &str {
+        &self.signature.description
+    }
+
+    fn extra_description(&self) -> &str {
+        &self.signature.extra_description
+    }
+
+    fn run(
+        &self,
+        engine_state: &EngineState,
+        stack: &mut Stack,
+        call: &Call,
+        input: PipelineData,
+    ) -> Result<PipelineData, ShellError> {
+        let mut args = FnArgs {
+            engine_state,
+            stack,
+            call,
+        };
+        let value = (self.callback)(&mut args, input)?;
+        Ok(PipelineData::Value(value, None))
+    }
+}
+
+impl NuInstance {
+    /// register a rust closure as command `name`.
+    /// the signature (`Signature::build(name)`) defines the arguments, flags, input/output
+    /// types, description, and category; the name is overwritten with `name`.
+    #[allow(clippy::result_large_err)]
+    pub fn register_fn(
+        &mut self,
+        name: &str,
+        mut signature: Signature,
+        callback: impl Fn(&mut FnArgs, PipelineData) -> Result<Value, ShellError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Result<(), ShellError> {
+        signature.name = name.into();
+        self.register_command(Box::new(FnCommand::new(signature, callback)))
+    }
+}
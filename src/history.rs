@@ -0,0 +1,303 @@
+use std::{
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use nu_protocol::{
+    engine::{EngineState, Stack},
+    HistoryFileFormat, ShellError,
+};
+use reedline::{History, HistoryItem, SearchDirection, SearchQuery, SqliteBackedHistory};
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub start: DateTime<FixedOffset>,
+    pub duration: Duration,
+    pub exit_status: i64,
+    pub cwd: String,
+}
+
+pub enum HereticHistory {
+    /// one entry per line: `start<TAB>duration_ms<TAB>exit_status<TAB>cwd<TAB>command`
+    Plaintext {
+        path: PathBuf,
+        max_size: usize,
+        cache: Option<PlaintextCache>,
+    },
+    Sqlite(Box<SqliteBackedHistory>),
+}
+
+/// the history of a `NuInstance`, shared with its history commands.
+/// opened on first use (after the config chose the format) and only reopened if
+/// `$env.config.history.file_format` changes.
+#[derive(Clone, Default)]
+pub struct SharedHistory(Arc<Mutex<Option<HereticHistory>>>);
+
+/// size and modification time, to notice changes of the plaintext file
+type FileStamp = (u64, Option<SystemTime>);
+
+/// the parsed plaintext history, reused while the file did not change
+/// (the example input reads the whole history before every prompt)
+pub struct PlaintextCache {
+    stamp: FileStamp,
+    entries: Vec<HistoryEntry>,
+}
+
+fn file_stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+pub fn history_path(format: HistoryFileFormat) -> Option<PathBuf> {
+    Some(crate::dirs::state_dir()?.join(match format {
+        HistoryFileFormat::Plaintext => "history.txt",
+        HistoryFileFormat::Sqlite => "history.sqlite3",
+    }))
+}
+
+fn history_error(msg: impl Into<String>) -> ShellError {
+    ShellError::GenericError {
+        error: "Heretic history failed".into(),
+        msg: msg.into(),
+        span: None,
+        help: None,
+        inner: vec![],
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+fn unescape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some(c) => res.push(c),
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+impl SharedHistory {
+    /// run `f` on the history, opening (and truncating) it first if needed
+    #[allow(clippy::result_large_err)]
+    pub fn with<T>(
+        &self,
+        engine_state: &EngineState,
+        stack: &Stack,
+        f: impl FnOnce(&mut HereticHistory) -> Result<T, ShellError>,
+    ) -> Result<T, ShellError> {
+        // a panic while holding the lock does not leave the history inconsistent
+        let mut guard = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let format = stack.get_config(engine_state).history.file_format;
+        let history = match guard.take() {
+            Some(history) if history.format() == format => history,
+            _ => {
+                let history = HereticHistory::open(engine_state, stack)?;
+                history.truncate()?;
+                history
+            }
+        };
+        f(guard.insert(history))
+    }
+
+    /// all entries, oldest first
+    #[allow(clippy::result_large_err)]
+    pub fn entries(
+        &self,
+        engine_state: &EngineState,
+        stack: &Stack,
+    ) -> Result<Vec<HistoryEntry>, ShellError> {
+        self.with(engine_state, stack, |history| history.entries())
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn push(
+        &self,
+        engine_state: &EngineState,
+        stack: &Stack,
+        entry: HistoryEntry,
+    ) -> Result<(), ShellError> {
+        self.with(engine_state, stack, |history| history.push(entry))
+    }
+}
+
+impl HereticHistory {
+    /// open the history file configured in `$env.config.history`
+    /// (see `truncate`, which `SharedHistory` runs once after opening)
+    #[allow(clippy::result_large_err)]
+    pub fn open(engine_state: &EngineState, stack: &Stack) -> Result<Self, ShellError> {
+        let config = stack.get_config(engine_state);
+        let format = config.history.file_format;
+        let path = history_path(format)
+            .ok_or_else(|| history_error("Unable to find a directory for the history file"))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| history_error(format!("Failed to create history dir: {e}")))?;
+        }
+        match format {
+            HistoryFileFormat::Plaintext => Ok(Self::Plaintext {
+                path,
+                max_size: config.history.max_size.max(0) as usize,
+                cache: None,
+            }),
+            HistoryFileFormat::Sqlite => Ok(Self::Sqlite(Box::new(
+                SqliteBackedHistory::with_file(path, None, None)
+                    .map_err(|e| history_error(format!("Failed to open history database: {e}")))?,
+            ))),
+        }
+    }
+
+    pub fn format(&self) -> HistoryFileFormat {
+        match self {
+            Self::Plaintext { .. } => HistoryFileFormat::Plaintext,
+            Self::Sqlite(_) => HistoryFileFormat::Sqlite,
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn push(&mut self, entry: HistoryEntry) -> Result<(), ShellError> {
+        match self {
+            Self::Plaintext { path, cache, .. } => {
+                let stamp_before = file_stamp(path);
+                let mut file = OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(path)
+                    .map_err(|e| history_error(format!("Failed to open history file: {e}")))?;
+                file.write_all(
+                    format!(
+                        "{}\t{}\t{}\t{}\t{}\n",
+                        entry.start.to_rfc3339(),
+                        entry.duration.as_millis(),
+                        entry.exit_status,
+                        escape(&entry.cwd),
+                        escape(&entry.command),
+                    )
+                    .as_bytes(),
+                )
+                .map_err(|e| history_error(format!("Failed to write history file: {e}")))?;
+                drop(file);
+
+                // keep the cache if nothing else wrote to the file in between
+                if let Some(cached) = cache.as_mut() {
+                    match file_stamp(path) {
+                        Some(stamp) if Some(cached.stamp) == stamp_before => {
+                            cached.stamp = stamp;
+                            cached.entries.push(entry);
+                        }
+                        _ => *cache = None,
+                    }
+                }
+                Ok(())
+            }
+            Self::Sqlite(history) => {
+                let mut item = HistoryItem::from_command_line(entry.command);
+                item.start_timestamp = Some(entry.start.with_timezone(&Utc));
+                item.duration = Some(entry.duration);
+                item.exit_status = Some(entry.exit_status);
+                item.cwd = Some(entry.cwd);
+                history
+                    .save(item)
+                    .map(|_| ())
+                    .map_err(|e| history_error(format!("Failed to write history database: {e}")))
+            }
+        }
+    }
+
+    /// all entries, oldest first
+    #[allow(clippy::result_large_err)]
+    pub fn entries(&mut self) -> Result<Vec<HistoryEntry>, ShellError> {
+        match self {
+            Self::Plaintext { path, cache, .. } => {
+                let Some(stamp) = file_stamp(path).filter(|_| path.is_file()) else {
+                    return Ok(vec![]);
+                };
+                if let Some(cached) = cache.as_ref().filter(|cached| cached.stamp == stamp) {
+                    return Ok(cached.entries.clone());
+                }
+                let entries = Self::read_plaintext(path)?;
+                *cache = Some(PlaintextCache {
+                    stamp,
+                    entries: entries.clone(),
+                });
+                Ok(entries)
+            }
+            Self::Sqlite(history) => Ok(history
+                .search(SearchQuery::everything(SearchDirection::Forward, None))
+                .map_err(|e| history_error(format!("Failed to read history database: {e}")))?
+                .into_iter()
+                .map(|item| HistoryEntry {
+                    start: item.start_timestamp.unwrap_or_default().fixed_offset(),
+                    duration: item.duration.unwrap_or_default(),
+                    exit_status: item.exit_status.unwrap_or_default(),
+                    cwd: item.cwd.unwrap_or_default(),
+                    command: item.command_line,
+                })
+                .collect()),
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn read_plaintext(path: &Path) -> Result<Vec<HistoryEntry>, ShellError> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| history_error(format!("Failed to open history file: {e}")))?;
+        Ok(BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| {
+                let mut fields = line.splitn(5, '\t');
+                Some(HistoryEntry {
+                    start: DateTime::parse_from_rfc3339(fields.next()?).ok()?,
+                    duration: Duration::from_millis(fields.next()?.parse().ok()?),
+                    exit_status: fields.next()?.parse().ok()?,
+                    cwd: unescape(fields.next()?),
+                    command: unescape(fields.next()?),
+                })
+            })
+            .collect())
+    }
+
+    /// drop the oldest plaintext entries beyond `$env.config.history.max_size`
+    /// (rewrites the whole file, so only done once at startup)
+    #[allow(clippy::result_large_err)]
+    pub fn truncate(&self) -> Result<(), ShellError> {
+        let Self::Plaintext { path, max_size, .. } = self else {
+            return Ok(());
+        };
+        if !path.is_file() {
+            return Ok(());
+        }
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| history_error(format!("Failed to read history file: {e}")))?;
+        let lines: Vec<&str> = contents.lines().collect();
+        if lines.len() <= *max_size {
+            return Ok(());
+        }
+        let mut kept = lines[lines.len() - max_size..].join("\n");
+        if !kept.is_empty() {
+            kept.push('\n');
+        }
+        std::fs::write(path, kept)
+            .map_err(|e| history_error(format!("Failed to write history file: {e}")))
+    }
+}
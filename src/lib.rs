@@ -1,22 +1,214 @@
 pub mod ansi;
+pub mod builder;
 pub mod commands;
 pub mod debug_x;
+pub mod dirs;
+pub mod fn_command;
+pub mod history;
+pub mod line_editor;
+#[cfg(feature = "serde")]
+pub mod serde_value;
+pub mod signals;
 #[cfg(feature = "heretic_step_debug")]
 pub mod step_debug;
+pub mod terminal;
 
+use miette::Diagnostic;
+use nu_cmd_base::hook::{eval_env_change_hook, eval_hook, eval_hooks};
 use nu_engine::eval_block_with_early_return;
 use nu_protocol::engine::{EngineState, Stack, StateWorkingSet};
-use nu_protocol::{PipelineData, ShellError, Span, Value};
-use std::io::Read;
-use std::path::PathBuf;
+use nu_protocol::{ParseError, PipelineData, ShellError, Span, Value};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 
+/// a parse or compile error as `ShellError` (message, first label, and help are kept)
+fn diagnostic_to_shell_error(e: &dyn Diagnostic, span: Option<Span>) -> ShellError {
+    ShellError::GenericError {
+        error: e.to_string(),
+        msg: e
+            .labels()
+            .and_then(|mut labels| labels.next())
+            .and_then(|label| label.label().map(String::from))
+            .unwrap_or_else(|| "here".into()),
+        span,
+        help: e.help().map(|help| help.to_string()),
+        inner: vec![],
+    }
+}
+
+/// all parse errors as one error (the individual ones are attached as related errors)
+pub fn parse_errors_to_shell_error(errors: &[ParseError]) -> ShellError {
+    ShellError::GenericError {
+        error: match errors.len() {
+            1 => "Failed to parse code".into(),
+            n => format!("Failed to parse code ({n} errors)"),
+        },
+        msg: "parsing failed here".into(),
+        span: errors.first().map(|e| e.span()),
+        help: None,
+        inner: errors
+            .iter()
+            .map(|e| diagnostic_to_shell_error(e, Some(e.span())))
+            .collect(),
+    }
+}
+
+/// `parse_errors_to_shell_error` for code whose source is dropped with the working-set
+/// (`evil`): the error points at `span` and the failing code is quoted in the help
+pub fn detached_parse_errors_to_shell_error(
+    errors: &[ParseError],
+    working_set: &StateWorkingSet,
+    span: Span,
+) -> ShellError {
+    let mut error = parse_errors_to_shell_error(errors);
+    if let ShellError::GenericError {
+        span: error_span,
+        inner,
+        ..
+    } = &mut error
+    {
+        *error_span = Some(span);
+        for (inner, e) in inner.iter_mut().zip(errors) {
+            if let ShellError::GenericError { span, help, .. } = inner {
+                let at = format!(
+                    "at: {}",
+                    String::from_utf8_lossy(working_set.get_span_contents(e.span()))
+                );
+                *span = None;
+                *help = Some(match help.take() {
+                    Some(help) => format!("{help} ({at})"),
+                    None => at,
+                });
+            }
+        }
+    }
+    error
+}
+
+/// `text` as a nu raw-string literal (`r#'..'#`), with enough `#`s that the text can not end it
+fn raw_string(text: &str) -> String {
+    let mut hashes = "#".to_string();
+    while text.contains(&format!("'{hashes}")) {
+        hashes.push('#');
+    }
+    format!("r{hashes}'{text}'{hashes}")
+}
+
+/// a command-line argument of a script as nu code: plain words (and the values of
+/// `--flag=value`) stay bare so they are parsed like upstream, anything else becomes a raw-string
+fn script_arg(arg: &str) -> String {
+    fn is_plain(word: &str) -> bool {
+        !word.is_empty()
+            && word
+                .chars()
+                .all(|c| c.is_alphanumeric() || "_-+./:@%,~".contains(c))
+    }
+    if let Some((flag, value)) = arg.split_once('=') {
+        if flag.starts_with("--") && is_plain(flag) {
+            return format!(
+                "{flag}={}",
+                if is_plain(value) {
+                    value.to_string()
+                } else {
+                    raw_string(value)
+                }
+            );
+        }
+    }
+    if is_plain(arg) {
+        arg.to_string()
+    } else {
+        raw_string(arg)
+    }
+}
+
+/// which user config files `NuInstance::load_all_configs` loads
+#[derive(Clone, Debug, Default)]
+pub struct ConfigFiles {
+    pub env_config: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    /// `$env.heretic_nu_autoload_dirs`
+    pub autoload: bool,
+    /// run last (after the autoload dirs), set for login shells
+    pub login: Option<PathBuf>,
+    /// if the (env-)config, an autoload, or the login file fails: continue with only the default
+    /// config (and without any config if the default config fails)
+    pub safe_mode: bool,
+}
+
+impl ConfigFiles {
+    /// the config file from the config dir (if it exists) and the autoload dirs
+    pub fn user_default() -> Self {
+        Self {
+            env_config: None,
+            config: dirs::config_file().filter(|file| file.is_file()),
+            autoload: true,
+            login: None,
+            safe_mode: false,
+        }
+    }
+
+    /// `user_default` plus the login file (if it exists)
+    pub fn user_login() -> Self {
+        Self {
+            login: dirs::login_file().filter(|file| file.is_file()),
+            ..Self::user_default()
+        }
+    }
+}
+
+/// a entry of `$env.heretic_nu_autoload_dirs`, resolved
+enum Autoload {
+    File(PathBuf),
+    /// a dir with a `mod.nu`
+    Module(PathBuf),
+}
+
+/// a nu interpreter: engine-state (definitions, files, ..) and stack (variables, env, ..).
+/// create it with `NuInstance::new` or `NuInstance::builder`.
 pub struct NuInstance {
-    pub engine_state: EngineState,
-    pub stack: Stack,
+    engine_state: EngineState,
+    stack: Stack,
+    /// shared with `heretic history` and `heretic line-editor`
+    history: history::SharedHistory,
 }
 
 impl NuInstance {
+    /// wrap a existing engine-state and stack (for example clones from inside a command)
+    pub fn from_parts(engine_state: EngineState, stack: Stack) -> Self {
+        Self {
+            engine_state,
+            stack,
+            history: history::SharedHistory::default(),
+        }
+    }
+
+    pub fn into_parts(self) -> (EngineState, Stack) {
+        (self.engine_state, self.stack)
+    }
+
+    pub fn engine_state(&self) -> &EngineState {
+        &self.engine_state
+    }
+
+    pub fn engine_state_mut(&mut self) -> &mut EngineState {
+        &mut self.engine_state
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.stack
+    }
+
+    /// both at once (for `nu_engine::eval_*` functions)
+    pub fn parts_mut(&mut self) -> (&mut EngineState, &mut Stack) {
+        (&mut self.engine_state, &mut self.stack)
+    }
+
     pub fn add_var(
         &mut self,
         value: nu_protocol::Value,
@@ -54,10 +246,21 @@ impl NuInstance {
         if cfg!(feature = "nu_explore") {
             engine_state = nu_explore::add_explore_context(engine_state);
         }
-        let init_cwd = std::env::current_dir().expect("Failed to get CWD");
+        #[cfg(feature = "plugin")]
+        {
+            engine_state = nu_cmd_plugin::add_plugin_command_context(engine_state);
+        }
+        let init_cwd = std::env::current_dir().map_err(|e| ShellError::GenericError {
+            error: "Failed to get the current directory".into(),
+            msg: e.to_string(),
+            span: None,
+            help: None,
+            inner: vec![],
+        })?;
         nu_cli::gather_parent_env_vars(&mut engine_state, init_cwd.as_ref());
 
         engine_state.generate_nu_constant();
+        Self::add_heretic_constant(&mut engine_state)?;
 
         engine_state.add_env_var(
             "CMD_DURATION_MS".into(),
@@ -67,10 +270,12 @@ impl NuInstance {
         let mut res = Self {
             engine_state,
             stack: Stack::new(),
+            history: history::SharedHistory::default(),
         };
 
         res.stack.set_last_exit_code(0, Span::unknown());
 
+        let background_jobs = commands::jobs::BackgroundJobs::default();
         res.append_commands(vec![
             // things not in the standard scope for some reason
             Box::new(nu_cli::Print),
@@ -80,10 +285,33 @@ impl NuInstance {
             #[cfg(feature = "heretic_const_evil")]
             Box::new(commands::evil::ConstEvil),
             Box::new(commands::debug::HereticDebug),
+            Box::new(commands::complete::HereticComplete),
+            Box::new(commands::parse_check::HereticParseCheck),
+            Box::new(commands::prompt::HereticPromptMeasure),
             #[cfg(feature = "heretic_test")]
             Box::new(commands::run_tests::HereticTestsRun),
             Box::new(commands::here_span::HereSpanCommand),
             Box::new(commands::here_span::GetSpanCommand),
+            Box::new(commands::history::HereticHistoryCommand {
+                history: res.history.clone(),
+            }),
+            Box::new(commands::edit_buffer::HereticEditBuffer),
+            Box::new(commands::config_reload::HereticConfigReload),
+            Box::new(commands::keybindings::HereticKeybindings),
+            Box::new(commands::keybindings::HereticKeyName),
+            Box::new(commands::line_editor::HereticLineEditor {
+                history: res.history.clone(),
+            }),
+            Box::new(commands::jobs::HereticJobs {
+                background: background_jobs.clone(),
+            }),
+            Box::new(commands::jobs::HereticFg {
+                background: background_jobs.clone(),
+            }),
+            #[cfg(unix)]
+            Box::new(commands::jobs::HereticBg {
+                background: background_jobs,
+            }),
             // overrides
             Box::new(commands::version::HereticVersion),
         ])?;
@@ -91,6 +319,30 @@ impl NuInstance {
         Ok(res)
     }
 
+    /// `$heretic`: the resolved heretic_nu directories (like `$nu`)
+    #[allow(clippy::result_large_err)]
+    fn add_heretic_constant(engine_state: &mut EngineState) -> Result<(), ShellError> {
+        let value = dirs::heretic_constant(Span::unknown());
+        let mut working_set = StateWorkingSet::new(engine_state);
+        let var_id = working_set.add_variable(
+            b"$heretic".to_vec(),
+            Span::unknown(),
+            value.get_type(),
+            false,
+        );
+        working_set.set_variable_const_val(var_id, value);
+        let delta = working_set.render();
+        engine_state.merge_delta(delta)
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn register_command(
+        &mut self,
+        command: Box<dyn nu_protocol::engine::Command>,
+    ) -> Result<(), ShellError> {
+        self.append_commands(vec![command])
+    }
+
     #[allow(clippy::result_large_err)]
     pub fn append_commands(
         &mut self,
@@ -111,9 +363,36 @@ impl NuInstance {
         &mut self,
         code: &str,
     ) -> Result<std::sync::Arc<nu_protocol::ast::Block>, ShellError> {
+        self.compile_source(None, code)
+    }
+
+    /// compile code, which came from `file` (if set) so spans and `path self` point at it
+    #[allow(clippy::result_large_err)]
+    fn compile_source(
+        &mut self,
+        file: Option<&Path>,
+        code: &str,
+    ) -> Result<std::sync::Arc<nu_protocol::ast::Block>, ShellError> {
+        let fname = file.map(|file| file.to_string_lossy().into_owned());
         let mut working_set = StateWorkingSet::new(&self.engine_state);
+        if let Some(file) = file {
+            working_set
+                .files
+                .push(file.to_path_buf(), Span::unknown())
+                .map_err(|e| parse_errors_to_shell_error(&[e]))?;
+        }
         let mut block: std::sync::Arc<nu_protocol::ast::Block> =
-            nu_parser::parse(&mut working_set, None, code.as_bytes(), false);
+            nu_parser::parse(&mut working_set, fname.as_deref(), code.as_bytes(), false);
+        if file.is_some() {
+            working_set.files.pop();
+        }
+        let fname = fname.as_deref().unwrap_or("source");
+        if !working_set.parse_errors.is_empty() {
+            let error = parse_errors_to_shell_error(&working_set.parse_errors);
+            drop(working_set);
+            self.keep_source(fname, code.as_bytes())?;
+            return Err(error);
+        }
         if block.ir_block.is_none() {
             let block_mut = Arc::make_mut(&mut block);
             match nu_engine::compile(&working_set, block_mut) {
@@ -121,48 +400,14 @@ impl NuInstance {
                     block_mut.ir_block = Some(ir_block);
                 }
                 Err(err) => {
-                    let msg = format!("Compiling IR failed: {err:?}");
-                    let span: Option<Span> = match err {
-                        nu_protocol::CompileError::DataOverflow { block_span } |
-                        nu_protocol::CompileError::FileOverflow { block_span } |
-                        nu_protocol::CompileError::IncoherentLoopState { block_span } |
-                        nu_protocol::CompileError::RegisterOverflow { block_span } => block_span,
-
-                        nu_protocol::CompileError::RegisterUninitialized { .. } => None,
-
-                        nu_protocol::CompileError::SetBranchTargetOfNonBranchInstruction { span, .. } |
-                        nu_protocol::CompileError::RunExternalNotFound { span } |
-                        nu_protocol::CompileError::AssignmentRequiresVar { span } |
-                        nu_protocol::CompileError::AssignmentRequiresMutableVar { span } |
-                        nu_protocol::CompileError::AutomaticEnvVarSetManually { span, .. } |
-                        nu_protocol::CompileError::CannotReplaceEnv { span } |
-                        nu_protocol::CompileError::UnexpectedExpression { span, .. } |
-                        nu_protocol::CompileError::MissingRequiredDeclaration { span, .. } |
-                        nu_protocol::CompileError::InvalidLiteral { span, .. } |
-                        nu_protocol::CompileError::Garbage { span } |
-                        nu_protocol::CompileError::UnsupportedOperatorExpression { span } |
-                        nu_protocol::CompileError::AccessEnvByInt { span } |
-                        nu_protocol::CompileError::InvalidKeywordCall { span, .. } |
-                        nu_protocol::CompileError::InvalidRedirectMode { span } |
-                        nu_protocol::CompileError::RegisterUninitializedWhilePushingInstruction { span, .. } => Some(span),
-
-                        nu_protocol::CompileError::NotInATry { span, .. } |
-                        nu_protocol::CompileError::NotInALoop { span, .. } |
-                        nu_protocol::CompileError::UndefinedLabel { span, .. } => span,
-                    };
-                    working_set.compile_errors.push(err);
-                    return Err(if let Some(span) = span {
-                        let txt = String::from_utf8_lossy(working_set.get_span_contents(span));
-                        println!("{txt}");
-
-                        ShellError::NushellFailedSpanned {
-                            msg,
-                            label: format!("here: {txt}"),
-                            span,
-                        }
-                    } else {
-                        ShellError::NushellFailed { msg }
-                    });
+                    let span = err
+                        .labels()
+                        .and_then(|mut labels| labels.next())
+                        .map(|label| Span::new(label.offset(), label.offset() + label.len()));
+                    let error = diagnostic_to_shell_error(&err, span);
+                    drop(working_set);
+                    self.keep_source(fname, code.as_bytes())?;
+                    return Err(error);
                 }
             };
         }
@@ -170,6 +415,16 @@ impl NuInstance {
         Ok(block)
     }
 
+    /// register the source of code, which did not make it into the engine-state, so the spans
+    /// of its errors still point at it.
+    /// (a fresh working-set places it at the same offset the failed parse used)
+    #[allow(clippy::result_large_err)]
+    fn keep_source(&mut self, fname: &str, code: &[u8]) -> Result<(), ShellError> {
+        let mut working_set = StateWorkingSet::new(&self.engine_state);
+        working_set.add_file(fname.into(), code);
+        self.engine_state.merge_delta(working_set.render())
+    }
+
     #[allow(clippy::result_large_err)]
     pub fn exec(
         &mut self,
@@ -177,11 +432,263 @@ impl NuInstance {
         pipeline_data: Option<PipelineData>,
     ) -> Result<PipelineData, ShellError> {
         let block = self.compile(line)?;
+        self.eval(&block, pipeline_data)
+    }
 
+    /// run code and collect the result into a value
+    #[allow(clippy::result_large_err)]
+    pub fn eval_to_value(&mut self, code: &str) -> Result<Value, ShellError> {
+        self.exec(code, None)?.into_value(Span::unknown())
+    }
+
+    /// run code and convert the result into a rust value (see `serde_value`)
+    #[cfg(feature = "serde")]
+    #[allow(clippy::result_large_err)]
+    pub fn eval_into<T: serde::de::DeserializeOwned>(
+        &mut self,
+        code: &str,
+    ) -> Result<T, ShellError> {
+        serde_value::from_value(&self.eval_to_value(code)?)
+    }
+
+    /// run code and collect the result into a string (lists are joined with newlines,
+    /// other values are converted like in string interpolation)
+    #[allow(clippy::result_large_err)]
+    pub fn eval_to_string(&mut self, code: &str) -> Result<String, ShellError> {
+        let config = self.stack.get_config(&self.engine_state);
+        self.exec(code, None)?.collect_string("\n", &config)
+    }
+
+    /// set `$name` for the code run afterwards (declared as `any`, so it can be set again with
+    /// values of other types)
+    #[allow(clippy::result_large_err)]
+    pub fn set_var(&mut self, name: &str, value: Value) -> Result<(), ShellError> {
+        let var_id = match self.find_var(name) {
+            Some(var_id) => var_id,
+            None => {
+                let mut working_set = StateWorkingSet::new(&self.engine_state);
+                let var_id = working_set.add_variable(
+                    format!("${name}").into_bytes(),
+                    Span::unknown(),
+                    nu_protocol::Type::Any,
+                    false,
+                );
+                self.engine_state.merge_delta(working_set.render())?;
+                var_id
+            }
+        };
+        self.stack.add_var(var_id, value);
+        Ok(())
+    }
+
+    /// `set_var` with a rust value (see `serde_value`)
+    #[cfg(feature = "serde")]
+    #[allow(clippy::result_large_err)]
+    pub fn set_var_from<T: serde::Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &T,
+    ) -> Result<(), ShellError> {
+        self.set_var(name, serde_value::to_value(value, Span::unknown())?)
+    }
+
+    /// the value of `$name` (variables and constants of the top-level scope)
+    pub fn get_var(&self, name: &str) -> Option<Value> {
+        let var_id = self.find_var(name)?;
+        self.stack
+            .get_var(var_id, Span::unknown())
+            .ok()
+            .or_else(|| self.engine_state.get_var(var_id).const_val.clone())
+    }
+
+    /// `get_var` converted into a rust value (see `serde_value`)
+    #[cfg(feature = "serde")]
+    #[allow(clippy::result_large_err)]
+    pub fn get_var_into<T: serde::de::DeserializeOwned>(
+        &self,
+        name: &str,
+    ) -> Result<Option<T>, ShellError> {
+        self.get_var(name)
+            .map(|value| serde_value::from_value(&value))
+            .transpose()
+    }
+
+    fn find_var(&self, name: &str) -> Option<nu_protocol::VarId> {
+        StateWorkingSet::new(&self.engine_state).find_variable(format!("${name}").as_bytes())
+    }
+
+    pub fn set_env_var(&mut self, name: &str, value: Value) {
+        self.stack.add_env_var(name.into(), value);
+    }
+
+    pub fn get_env_var(&self, name: &str) -> Option<Value> {
+        self.stack.get_env_var(&self.engine_state, name).cloned()
+    }
+
+    /// call a command (builtin or custom) like nu code would (with the type-checks, defaults,
+    /// and exit-code checks): `args` are the positional arguments, `named` the flags (switches
+    /// take a bool)
+    #[allow(clippy::result_large_err)]
+    pub fn call_decl(
+        &mut self,
+        name: &str,
+        args: Vec<Value>,
+        named: Vec<(&str, Value)>,
+        input: Option<PipelineData>,
+    ) -> Result<PipelineData, ShellError> {
+        use nu_protocol::ast::{Argument, Block, Call, Expr, Expression, Pipeline};
+
+        /// a argument as (anonymous) variable
+        fn arg_var(
+            working_set: &mut StateWorkingSet,
+            vars: &mut Vec<(nu_protocol::VarId, Value)>,
+            value: Value,
+        ) -> Expression {
+            let span = Span::unknown();
+            let ty = value.get_type();
+            let var_id = working_set.add_variable(b"$arg".to_vec(), span, ty.clone(), false);
+            vars.push((var_id, value));
+            Expression::new_unknown(Expr::Var(var_id), span, ty)
+        }
+
+        let span = Span::unknown();
+        let decl_id = self
+            .engine_state
+            .find_decl(name.as_bytes(), &[])
+            .ok_or_else(|| ShellError::GenericError {
+                error: "Command not found".into(),
+                msg: format!("there is no command named `{name}`"),
+                span: None,
+                help: None,
+                inner: vec![],
+            })?;
+        let signature = self.engine_state.get_decl(decl_id).signature();
+        if let Some((flag, _)) = named
+            .iter()
+            .find(|(flag, _)| signature.get_long_flag(flag).is_none())
+        {
+            return Err(ShellError::GenericError {
+                error: "Unknown flag".into(),
+                msg: format!("`{name}` has no flag `--{flag}`"),
+                span: None,
+                help: None,
+                inner: vec![],
+            });
+        }
+
+        // the variables are only declared in this working-set (which is discarded), so they
+        // do not end up in the scope, and their values only live on the stack during the call
+        let mut working_set = StateWorkingSet::new(&self.engine_state);
+        let mut vars = Vec::with_capacity(args.len() + named.len());
+        let mut arguments: Vec<Argument> = args
+            .into_iter()
+            .map(|value| Argument::Positional(arg_var(&mut working_set, &mut vars, value)))
+            .collect();
+        for (flag, value) in named {
+            arguments.push(Argument::Named((
+                nu_protocol::Spanned {
+                    item: flag.to_string(),
+                    span,
+                },
+                None,
+                Some(arg_var(&mut working_set, &mut vars, value)),
+            )));
+        }
+        let call = Call {
+            decl_id,
+            head: span,
+            arguments,
+            parser_info: std::collections::HashMap::new(),
+        };
+
+        // a block with only the call, so it runs like any other code
+        let mut block = Block::new();
+        block
+            .pipelines
+            .push(Pipeline::from_vec(vec![Expression::new_unknown(
+                Expr::Call(Box::new(call)),
+                span,
+                nu_protocol::Type::Any,
+            )]));
+        let ir_block = nu_engine::compile(&working_set, &block)
+            .map_err(|err| diagnostic_to_shell_error(&err, None))?;
+        block.ir_block = Some(ir_block);
+        drop(working_set);
+
+        let var_ids: Vec<nu_protocol::VarId> = vars.iter().map(|(var_id, _)| *var_id).collect();
+        for (var_id, value) in vars {
+            self.stack.add_var(var_id, value);
+        }
+        let res = self.eval(&block, input);
+        for var_id in var_ids {
+            self.stack.remove_var(var_id);
+        }
+        res
+    }
+
+    /// run a file in the current scope (like `source`), with `$env.FILE_PWD` and
+    /// `$env.CURRENT_FILE` set while it runs
+    #[allow(clippy::result_large_err)]
+    pub fn exec_file(
+        &mut self,
+        path: &Path,
+        pipeline_data: Option<PipelineData>,
+    ) -> Result<PipelineData, ShellError> {
+        let (path, block) = self.compile_file(path)?;
+        self.set_file_env(&path);
+        let res = self.eval(&block, pipeline_data);
+        self.stack.remove_env_var(&self.engine_state, "FILE_PWD");
+        self.stack
+            .remove_env_var(&self.engine_state, "CURRENT_FILE");
+        res
+    }
+
+    /// read and compile a file (spans point at its canonical path)
+    #[allow(clippy::result_large_err)]
+    fn compile_file(
+        &mut self,
+        path: &Path,
+    ) -> Result<(PathBuf, Arc<nu_protocol::ast::Block>), ShellError> {
+        let read_error = |msg: String| ShellError::GenericError {
+            error: "Failed to read file".into(),
+            msg,
+            span: None,
+            help: None,
+            inner: vec![],
+        };
+        let path = std::fs::canonicalize(path)
+            .map_err(|e| read_error(format!("{}: {e}", path.display())))?;
+        let code = std::fs::read_to_string(&path)
+            .map_err(|e| read_error(format!("{}: {e}", path.display())))?;
+        let block = self.compile_source(Some(&path), &code)?;
+        Ok((path, block))
+    }
+
+    /// `$env.FILE_PWD` and `$env.CURRENT_FILE`
+    fn set_file_env(&mut self, path: &Path) {
+        let span = Span::unknown();
+        if let Some(parent) = path.parent() {
+            self.stack.add_env_var(
+                "FILE_PWD".into(),
+                Value::string(parent.to_string_lossy(), span),
+            );
+        }
+        self.stack.add_env_var(
+            "CURRENT_FILE".into(),
+            Value::string(path.to_string_lossy(), span),
+        );
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn eval(
+        &mut self,
+        block: &nu_protocol::ast::Block,
+        pipeline_data: Option<PipelineData>,
+    ) -> Result<PipelineData, ShellError> {
         match eval_block_with_early_return::<nu_protocol::debugger::WithDebug>(
             &self.engine_state,
             &mut self.stack,
-            &block,
+            block,
             pipeline_data.unwrap_or(PipelineData::Empty),
         ) {
             Ok(res) => {
@@ -197,6 +704,8 @@ impl NuInstance {
         }
     }
 
+    /// run a script like upstream nu: the file itself, followed by its `main` command (if it
+    /// defines one) with the arguments, printing the result
     #[allow(clippy::result_large_err)]
     pub fn run_file(
         &mut self,
@@ -204,54 +713,84 @@ impl NuInstance {
         args: &[String],
         input: Option<PipelineData>,
     ) -> Result<(), ShellError> {
-        nu_cli::evaluate_file(
-            filepath,
-            args,
-            &mut self.engine_state,
-            &mut self.stack,
-            input.unwrap_or(PipelineData::Empty),
-        )
+        let main_before = self.engine_state.find_decl(b"main", &[]);
+        let (path, block) = self.compile_file(Path::new(&filepath))?;
+        // only a `main` of the file itself (not one of the config)
+        let has_main = self.engine_state.find_decl(b"main", &[]) != main_before;
+        // the env stays set for `main`
+        self.set_file_env(&path);
+        let output = if has_main {
+            self.eval(&block, None)?;
+            // upstream passes the arguments as code as well (so flags and types work)
+            let args: Vec<String> = args.iter().map(|arg| script_arg(arg)).collect();
+            self.exec(&format!("main {}", args.join(" ")), input)?
+        } else {
+            self.eval(&block, input)?
+        };
+        match output.into_value(Span::unknown())? {
+            Value::Nothing { .. } => {}
+            value => self.render_with_print(PipelineData::Value(value, None)),
+        }
+        Ok(())
     }
 
     pub fn render(&mut self, result: Result<PipelineData, ShellError>) {
         match result {
             Ok(pipeline_data) => match pipeline_data.into_value(Span::unknown()) {
-                Ok(value) => match value {
-                    Value::Nothing { .. } => println!(),
-                    _ => match self.exec("print", Some(PipelineData::Value(value, None))) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            eprintln!("RENDER FAILED:");
-                            self.render(Err(e));
+                Ok(value) => match (
+                    value,
+                    self.stack
+                        .get_config(&self.engine_state)
+                        .hooks
+                        .display_output
+                        .clone(),
+                ) {
+                    (Value::Nothing { .. }, _) => println!(),
+                    (value, Some(hook)) => {
+                        match eval_hook(
+                            &mut self.engine_state,
+                            &mut self.stack,
+                            Some(PipelineData::Value(value, None)),
+                            vec![],
+                            &hook,
+                            "display_output",
+                        ) {
+                            Ok(PipelineData::Empty) => println!(),
+                            Ok(output) => self.render_with_print(output),
+                            Err(e) => {
+                                eprintln!("display_output hook failed:");
+                                self.render_error(&e);
+                            }
                         }
-                    },
+                    }
+                    (value, None) => self.render_with_print(PipelineData::Value(value, None)),
                 },
-                Err(e) => eprintln!("Conversion-Error (into_value): {:?}", e),
+                Err(e) => self.render_error(&e),
             },
-            Err(render_error) => {
-                eprintln!("Nu-Error: {:?}", render_error);
-                #[allow(clippy::single_match)]
-                match render_error {
-                    ShellError::VariableNotFoundAtRuntime { span } => {
-                        let span_contents = self.engine_state.get_span_contents(span);
-                        if let Ok(a) = std::str::from_utf8(span_contents) {
-                            eprintln!("Span contents: {a}");
-                        }
-                    }
-                    _ => (),
-                }
-            }
+            Err(e) => self.render_error(&e),
+        }
+    }
+
+    fn render_with_print(&mut self, output: PipelineData) {
+        if let Err(e) = self.exec("print", Some(output)) {
+            eprintln!("RENDER FAILED:");
+            self.render_error(&e);
         }
     }
 
-    pub fn load_default_config(&mut self) {
-        self.exec(nu_utils::utils::ConfigFileKind::Env.default(), None)
-            .expect("failed to run upstream default_env.nu");
-        self.exec(nu_utils::utils::ConfigFileKind::Config.default(), None)
-            .expect("failed to run upstream default_config.nu");
+    /// print a error as diagnostic (source snippet, labels, help, related errors).
+    /// honours `$env.config.error_style`.
+    pub fn render_error(&self, error: &ShellError) {
+        nu_protocol::report_shell_error(Some(&self.stack), &self.engine_state, error);
+    }
 
-        self.exec(include_str!("default_config.nu"), None)
-            .expect("Default config is invalid");
+    /// upstream's `default_env.nu` and `default_config.nu`, followed by heretic's default config
+    #[allow(clippy::result_large_err)]
+    pub fn load_default_config(&mut self) -> Result<(), ShellError> {
+        self.exec(nu_utils::utils::ConfigFileKind::Env.default(), None)?;
+        self.exec(nu_utils::utils::ConfigFileKind::Config.default(), None)?;
+        self.exec(include_str!("default_config.nu"), None)?;
+        Ok(())
     }
 
     //#[allow(clippy::result_large_err)]
@@ -285,80 +824,648 @@ impl NuInstance {
     //    Ok(())
     //}
 
+    /// register the plugins of a plugin registry file (default: `plugin.msgpackz` in the data dir).
+    /// `plugin add` and `plugin rm` write to the same file (`$nu.plugin-path`).
+    #[cfg(feature = "plugin")]
+    pub fn load_plugins(&mut self, file: Option<PathBuf>) {
+        let Some(file) = file.or_else(dirs::plugin_file) else {
+            return;
+        };
+        // the file itself does not have to exist, but its dir does
+        if let Some(dir) = file.parent() {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                eprintln!(
+                    "Error: failed to create the plugin dir {}: {e}",
+                    dir.display()
+                );
+                return;
+            }
+        }
+        nu_cli::read_plugin_file(
+            &mut self.engine_state,
+            Some(nu_protocol::Spanned {
+                item: file.to_string_lossy().into_owned(),
+                span: Span::unknown(),
+            }),
+        );
+        // `$nu.plugin-path`
+        self.engine_state.generate_nu_constant();
+    }
+
+    /// the default config, followed by the user config files.
+    /// errors are reported and only skip the failing file, returns false if there were any.
+    pub fn load_all_configs(&mut self, files: &ConfigFiles) -> bool {
+        self.run_configs(files, |ni, source, e| {
+            eprintln!("Error in {source}:");
+            ni.render_error(&e);
+            true
+        })
+    }
+
+    /// like `load_all_configs`, but stops at the first error and returns it (nothing is printed)
     #[allow(clippy::result_large_err)]
-    pub fn load_all_configs(&mut self) -> Result<(), ShellError> {
-        self.load_default_config();
-
-        if let Some(home_dir) = std::env::home_dir() {
-            let config_file = home_dir
-                .join(".config")
-                .join("heretic_nu")
-                .join("config.nu");
-            if config_file.is_file() {
-                let mut script = String::new();
-                std::fs::File::open(config_file)
-                    .expect("File not found.")
-                    .read_to_string(&mut script)
-                    .expect("Failed to read config.nu file (filesystem IO)");
-                self.exec(&script, None)?;
+    pub fn try_load_all_configs(&mut self, files: &ConfigFiles) -> Result<(), ShellError> {
+        let mut error = None;
+        self.run_configs(files, |_, source, e| {
+            error = Some(ShellError::GenericError {
+                error: format!("Error in {source}"),
+                msg: e.to_string(),
+                span: None,
+                help: None,
+                inner: vec![e],
+            });
+            false
+        });
+        error.map_or(Ok(()), Err)
+    }
+
+    /// load the configs, `on_error(self, source, error)` returns whether to continue.
+    /// returns false if anything failed.
+    fn run_configs(
+        &mut self,
+        files: &ConfigFiles,
+        mut on_error: impl FnMut(&Self, String, ShellError) -> bool,
+    ) -> bool {
+        let bare = files
+            .safe_mode
+            .then(|| (self.engine_state.clone(), self.stack.clone()));
+        if let Err(e) = self.load_default_config() {
+            on_error(self, "the default config".into(), e);
+            if let Some((engine_state, stack)) = bare {
+                eprintln!("Safe-mode: the default config failed, continuing without any config");
+                self.engine_state = engine_state;
+                self.stack = stack;
             }
-            let ev = self
-                .engine_state
-                .get_env_var("heretic_nu_autoload_dirs")
-                .cloned();
-            match ev {
-                Some(Value::List { vals, .. }) => {
-                    for val in vals {
-                        match val {
-                            Value::String { val, .. } => {
-                                let fp = PathBuf::from(val);
-                                if fp.is_dir() {
-                                    for f in std::fs::read_dir(fp)
-                                        .expect("Failed to read autoload-dir contents")
-                                    {
-                                        let f: std::fs::DirEntry =
-                                            f.expect("Failed to read autoload-dir contents");
-                                        let p = f.path();
-                                        if p.is_file()
-                                            && p.extension() == Some(std::ffi::OsStr::new("nu"))
-                                        {
-                                            let mut script = String::new();
-                                            std::fs::File::open(p)
-                                                .expect("File not found.")
-                                                .read_to_string(&mut script).expect("Failed to read autoload-dir file (filesystem IO)");
-                                            self.exec(&script, None)?;
-                                        }
-                                    }
-                                }
-                            }
-                            _ => {
-                                return Err(ShellError::TypeMismatch {
-                                    err_message:
-                                        "$env.heretic_nu_autoload_dirs has to be a list<path>"
-                                            .into(),
-                                    span: Span::unknown(),
-                                });
-                            }
-                        }
+            return false;
+        }
+        let mut snapshot = files
+            .safe_mode
+            .then(|| (self.engine_state.clone(), self.stack.clone()));
+
+        let mut ok = true;
+        for path in [&files.env_config, &files.config].into_iter().flatten() {
+            if let Err(e) = self.exec_file(path, None) {
+                ok = false;
+                if !on_error(self, format!("config file {}", path.display()), e) {
+                    return false;
+                }
+            }
+        }
+        if self.safe_mode_fallback(ok, &mut snapshot) {
+            return false;
+        }
+
+        if files.autoload {
+            for autoload in self.autoloads() {
+                let (res, source) = match &autoload {
+                    Autoload::File(path) => (
+                        self.exec_file(path, None),
+                        format!("autoload file {}", path.display()),
+                    ),
+                    Autoload::Module(dir) => (
+                        self.exec(
+                            &format!("use {} *", raw_string(&dir.to_string_lossy())),
+                            None,
+                        ),
+                        format!("autoload module {}", dir.display()),
+                    ),
+                };
+                if let Err(e) = res {
+                    ok = false;
+                    if !on_error(self, source, e) {
+                        return false;
                     }
                 }
-                Some(_) => {
-                    return Err(ShellError::TypeMismatch {
-                        err_message: "$env.heretic_nu_autoload_dirs has to be a list<path>".into(),
-                        span: Span::unknown(),
-                    });
+            }
+        }
+        if self.safe_mode_fallback(ok, &mut snapshot) {
+            return false;
+        }
+
+        if let Some(path) = &files.login {
+            if let Err(e) = self.exec_file(path, None) {
+                on_error(self, format!("login file {}", path.display()), e);
+                ok = false;
+            }
+        }
+        self.safe_mode_fallback(ok, &mut snapshot);
+        ok
+    }
+
+    /// safe-mode (`snapshot` is set): if something failed, go back to only the default config.
+    /// returns whether it did.
+    fn safe_mode_fallback(
+        &mut self,
+        ok: bool,
+        snapshot: &mut Option<(EngineState, Stack)>,
+    ) -> bool {
+        if ok {
+            return false;
+        }
+        let Some((engine_state, stack)) = snapshot.take() else {
+            return false;
+        };
+        eprintln!("Safe-mode: the config failed, continuing with the default config only");
+        self.engine_state = engine_state;
+        self.stack = stack;
+        true
+    }
+
+    /// `load_all_configs` in a running session (without the login file, it only runs at startup).
+    /// the configs run on top of the current state, so a reload only adds and replaces
+    /// definitions and env vars: ones removed from a config stay until the shell is restarted.
+    /// if anything fails, the errors are reported and the state from before the reload is kept.
+    pub fn reload_configs(&mut self, files: &ConfigFiles) -> bool {
+        let snapshot = (self.engine_state.clone(), self.stack.clone());
+        let files = ConfigFiles {
+            login: None,
+            ..files.clone()
+        };
+        if self.load_all_configs(&files) {
+            return true;
+        }
+        eprintln!("Config reload failed, keeping the previous config");
+        (self.engine_state, self.stack) = snapshot;
+        false
+    }
+
+    /// whether `heretic config reload` was called since the last check
+    pub fn take_config_reload_request(&mut self) -> bool {
+        let requested = self
+            .stack
+            .get_env_var(
+                &self.engine_state,
+                commands::config_reload::CONFIG_RELOAD_REQUEST,
+            )
+            .is_some_and(|v| v.is_true());
+        if requested {
+            self.stack.remove_env_var(
+                &self.engine_state,
+                commands::config_reload::CONFIG_RELOAD_REQUEST,
+            );
+        }
+        requested
+    }
+
+    /// modification times of the reloaded config files (not the login file), the autoload dirs,
+    /// and the files in them (compared before each prompt if `$env.heretic_nu_config_watch` is
+    /// true, so changes are only noticed at the next prompt)
+    pub fn config_stamp(&self, files: &ConfigFiles) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let mut paths: Vec<PathBuf> = [&files.env_config, &files.config]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        if files.autoload {
+            for (dir, _) in self.autoload_dirs(false) {
+                if let Ok(entries) = std::fs::read_dir(&dir) {
+                    let mut entries: Vec<PathBuf> = entries
+                        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|path| path.is_file())
+                        .collect();
+                    entries.sort();
+                    paths.extend(entries);
+                }
+                paths.push(dir);
+            }
+        }
+        paths
+            .into_iter()
+            .map(|path| {
+                let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, modified)
+            })
+            .collect()
+    }
+
+    /// `$env.heretic_nu_config_watch`
+    pub fn config_watch_enabled(&self) -> bool {
+        self.stack
+            .get_env_var(&self.engine_state, "heretic_nu_config_watch")
+            .is_some_and(|v| v.is_true())
+    }
+
+    /// what to load from `$env.heretic_nu_autoload_dirs`.
+    /// entries are dirs of `.nu` files (run sorted by name) or `{path: <dir>, mode: 'module'}`
+    /// records for dirs with a `mod.nu` (loaded as `use <dir> *`).
+    /// problems with the entries themselves are reported and skipped.
+    fn autoloads(&self) -> Vec<Autoload> {
+        let mut res = vec![];
+        for (dir, module) in self.autoload_dirs(true) {
+            if module {
+                res.push(Autoload::Module(dir));
+                continue;
+            }
+            let mut files: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| {
+                        path.is_file() && path.extension() == Some(std::ffi::OsStr::new("nu"))
+                    })
+                    .collect(),
+                Err(e) => {
+                    eprintln!("Error: failed to read autoload dir {}: {e}", dir.display());
+                    continue;
+                }
+            };
+            files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+            res.extend(files.into_iter().map(Autoload::File));
+        }
+        res
+    }
+
+    /// the existing dirs of `$env.heretic_nu_autoload_dirs` (and whether they are modules),
+    /// invalid entries are skipped (and reported if `report`)
+    fn autoload_dirs(&self, report: bool) -> Vec<(PathBuf, bool)> {
+        let type_error = |span: Span| {
+            if report {
+                self.render_error(&ShellError::TypeMismatch {
+                    err_message: "$env.heretic_nu_autoload_dirs has to be a list<path | record<path: path, mode: string>>".into(),
+                    span,
+                });
+            }
+        };
+        let entries = match self
+            .stack
+            .get_env_var(&self.engine_state, "heretic_nu_autoload_dirs")
+        {
+            None | Some(Value::Nothing { .. }) => return vec![],
+            Some(Value::List { vals, .. }) => vals.clone(),
+            Some(other) => {
+                type_error(other.span());
+                return vec![];
+            }
+        };
+
+        let mut res = vec![];
+        for entry in entries {
+            let (dir, module) = match &entry {
+                Value::String { val, .. } => (PathBuf::from(val), false),
+                Value::Record { val, .. } => match (val.get("path"), val.get("mode")) {
+                    (Some(Value::String { val: path, .. }), None) => (PathBuf::from(path), false),
+                    (
+                        Some(Value::String { val: path, .. }),
+                        Some(Value::String { val: mode, .. }),
+                    ) if mode == "files" || mode == "module" => {
+                        (PathBuf::from(path), mode == "module")
+                    }
+                    _ => {
+                        type_error(entry.span());
+                        continue;
+                    }
+                },
+                _ => {
+                    type_error(entry.span());
+                    continue;
                 }
-                None => {}
+            };
+            if dir.is_dir() {
+                res.push((dir, module));
             }
         }
-        Ok(())
+        res
+    }
+
+    /// run `$env.config.hooks.pre_prompt` (before the prompt, followed by `run_env_change_hooks`)
+    #[allow(clippy::result_large_err)]
+    pub fn run_pre_prompt_hooks(&mut self) -> Result<(), ShellError> {
+        let hooks = self.stack.get_config(&self.engine_state).hooks.clone();
+        eval_hooks(
+            &mut self.engine_state,
+            &mut self.stack,
+            vec![],
+            &hooks.pre_prompt,
+            "pre_prompt",
+        )
+    }
+
+    /// run `$env.config.hooks.pre_execution` (after the input, before running it)
+    #[allow(clippy::result_large_err)]
+    pub fn run_pre_execution_hooks(&mut self) -> Result<(), ShellError> {
+        let hooks = self.stack.get_config(&self.engine_state).hooks.clone();
+        eval_hooks(
+            &mut self.engine_state,
+            &mut self.stack,
+            vec![],
+            &hooks.pre_execution,
+            "pre_execution",
+        )
+    }
+
+    /// run `$env.config.hooks.env_change.<VAR>` hooks for all variables, which changed since
+    /// the last call (with `$before` and `$after`)
+    #[allow(clippy::result_large_err)]
+    pub fn run_env_change_hooks(&mut self) -> Result<(), ShellError> {
+        let hooks = self.stack.get_config(&self.engine_state).hooks.clone();
+        eval_env_change_hook(&hooks.env_change, &mut self.engine_state, &mut self.stack)
+    }
+
+    /// check `$env.config.hooks` for unknown hooks and invalid hook values
+    pub fn validate_hooks(&self) -> Vec<String> {
+        fn is_valid_hook(hook: &Value, allow_list: bool) -> bool {
+            match hook {
+                Value::Closure { .. } | Value::String { .. } | Value::Nothing { .. } => true,
+                Value::Record { val, .. } => {
+                    matches!(
+                        val.get("condition"),
+                        None | Some(Value::Closure { .. } | Value::Nothing { .. })
+                    ) && val
+                        .get("code")
+                        .is_some_and(|code| is_valid_hook(code, false))
+                }
+                Value::List { vals, .. } => {
+                    allow_list && vals.iter().all(|v| is_valid_hook(v, false))
+                }
+                _ => false,
+            }
+        }
+
+        let Some(hooks) = self
+            .stack
+            .get_env_var(&self.engine_state, "config")
+            .and_then(|config| config.get_data_by_key("hooks"))
+        else {
+            return vec![];
+        };
+        let Value::Record { val: hooks, .. } = hooks else {
+            return vec!["$env.config.hooks has to be a record".into()];
+        };
+        let mut problems = Vec::new();
+        for (name, hook) in hooks.iter() {
+            let valid = match name.as_str() {
+                "pre_prompt" | "pre_execution" => is_valid_hook(hook, true),
+                "display_output" | "command_not_found" => {
+                    matches!(
+                        hook,
+                        Value::Closure { .. } | Value::String { .. } | Value::Nothing { .. }
+                    )
+                }
+                "env_change" => match hook {
+                    Value::Record { val, .. } => val.iter().all(|(_, v)| is_valid_hook(v, true)),
+                    Value::Nothing { .. } => true,
+                    _ => false,
+                },
+                _ => {
+                    problems.push(format!("$env.config.hooks.{name}: unknown hook"));
+                    continue;
+                }
+            };
+            if !valid {
+                problems.push(format!(
+                    "$env.config.hooks.{name}: invalid hook value (type: {})",
+                    hook.get_type()
+                ));
+            }
+        }
+        problems
+    }
+
+    /// open the history (`$env.config.history`) now instead of on first use, to report
+    /// problems before the first command
+    #[allow(clippy::result_large_err)]
+    pub fn open_history(&self) -> Result<(), ShellError> {
+        self.history
+            .with(&self.engine_state, &self.stack, |_history| Ok(()))
+    }
+
+    /// add a REPL command to the history
+    #[allow(clippy::result_large_err)]
+    pub fn push_history(&self, entry: history::HistoryEntry) -> Result<(), ShellError> {
+        self.history.push(&self.engine_state, &self.stack, entry)
+    }
+
+    /// set `$env.LAST_RESULT` and `$env.CMD_DURATION` after a REPL command.
+    /// the value itself is only kept if `$env.heretic_nu_keep_last_value` is true (it can be big).
+    /// `$env.CMD_DURATION_MS` (a string, like upstream sets it) is only kept for backward
+    /// compatibility, use `$env.CMD_DURATION` or `$env.LAST_RESULT.duration` instead.
+    pub fn set_last_result(
+        &mut self,
+        command: &str,
+        result: &Result<Value, ShellError>,
+        exit_code: i32,
+        start: chrono::DateTime<chrono::FixedOffset>,
+        duration: std::time::Duration,
+    ) {
+        let span = Span::unknown();
+        let keep_value = self
+            .stack
+            .get_env_var(&self.engine_state, "heretic_nu_keep_last_value")
+            .is_some_and(|v| v.is_true());
+        let end = start + chrono::Duration::from_std(duration).unwrap_or_default();
+        let duration_value = Value::duration(duration.as_nanos() as i64, span);
+
+        let last_result = Value::record(
+            nu_protocol::record! {
+                "command" => Value::string(command, span),
+                "exit_code" => Value::int(exit_code as i64, span),
+                "errored" => Value::bool(result.is_err(), span),
+                "error" => match result {
+                    Err(e) => Value::string(e.to_string(), span),
+                    Ok(_) => Value::nothing(span),
+                },
+                "start" => Value::date(start, span),
+                "end" => Value::date(end, span),
+                "duration" => duration_value.clone(),
+                "value" => match result {
+                    Ok(value) if keep_value => value.clone(),
+                    _ => Value::nothing(span),
+                },
+            },
+            span,
+        );
+        self.stack.add_env_var("LAST_RESULT".into(), last_result);
+        self.stack
+            .add_env_var("CMD_DURATION".into(), duration_value);
+        // backward compatibility alias of `CMD_DURATION`
+        self.stack.add_env_var(
+            "CMD_DURATION_MS".into(),
+            Value::string(duration.as_millis().to_string(), span),
+        );
     }
 
     pub fn set_exitcode(&mut self, code: i32, span: Span) {
         self.stack.set_last_exit_code(code, span);
     }
 
+    /// clear a ctrl-c from the previous command (see `signals::ctrlc_protection`)
+    pub fn reset_signals(&mut self) {
+        self.engine_state.reset_signals();
+    }
+
+    /// `$nu.is-login`
+    pub fn set_login(&mut self, is_login: bool) {
+        self.engine_state.is_login = is_login;
+        // `$nu` is a constant, computed from the engine state
+        self.engine_state.generate_nu_constant();
+    }
+
     pub fn set_interactive(&mut self, is_interactive: bool) {
         self.engine_state.is_interactive = is_interactive;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_string_delimiter() {
+        assert_eq!(raw_string("/a/b"), "r#'/a/b'#");
+        assert_eq!(raw_string("/a'#b"), "r##'/a'#b'##");
+        assert_eq!(raw_string("/a'##'#b"), "r###'/a'##'#b'###");
+    }
+
+    #[test]
+    fn var_round_trip() {
+        let mut nu = NuInstance::new().unwrap();
+        nu.set_var("x", Value::test_int(5)).unwrap();
+        assert_eq!(nu.eval_to_value("$x + 1").unwrap().as_int().unwrap(), 6);
+        nu.set_var("x", Value::test_string("five")).unwrap();
+        assert_eq!(nu.get_var("x").unwrap().as_str().unwrap(), "five");
+        assert!(nu.get_var("y").is_none());
+        assert_eq!(nu.eval_to_string("[1 2]").unwrap(), "1\n2");
+    }
+
+    #[test]
+    fn builder() {
+        let mut nu = NuInstance::builder()
+            .with_env_var("greeting", Value::test_string("hello"))
+            .with_var("name", Value::test_string("world"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            nu.eval_to_string("$'($env.greeting) ($name)'").unwrap(),
+            "hello world"
+        );
+    }
+
+    #[test]
+    fn call_custom_command() {
+        let mut nu = NuInstance::new().unwrap();
+        nu.exec(
+            "def f [a: int, b?: int = 10, ...rest: int, --times: int = 1] { ($a + $b + ($rest | length)) * $times }",
+            None,
+        )
+        .unwrap();
+        let mut call = |args: Vec<i64>, named: Vec<(&str, Value)>| {
+            nu.call_decl(
+                "f",
+                args.into_iter().map(Value::test_int).collect(),
+                named,
+                None,
+            )
+            .and_then(|data| data.into_value(Span::test_data()))
+            .map(|value| value.as_int().unwrap())
+        };
+        assert_eq!(call(vec![1], vec![]).unwrap(), 11);
+        assert_eq!(call(vec![1, 2, 3, 4], vec![]).unwrap(), 5);
+        assert_eq!(
+            call(vec![1], vec![("times", Value::test_int(2))]).unwrap(),
+            22
+        );
+        assert!(call(vec![], vec![]).is_err());
+        assert!(call(vec![1], vec![("nope", Value::test_int(2))]).is_err());
+        // type-checked like a call in nu code
+        assert!(nu
+            .call_decl("f", vec![Value::test_string("1")], vec![], None)
+            .is_err());
+    }
+
+    #[test]
+    fn call_builtin_with_flag() {
+        let mut nu = NuInstance::new().unwrap();
+        let res = nu
+            .call_decl(
+                "str contains",
+                vec![Value::test_string("A")],
+                vec![("ignore-case", Value::test_bool(true))],
+                Some(PipelineData::Value(Value::test_string("abc"), None)),
+            )
+            .unwrap()
+            .into_value(Span::test_data())
+            .unwrap();
+        assert!(res.as_bool().unwrap());
+    }
+
+    #[test]
+    fn call_unknown_decl() {
+        let mut nu = NuInstance::new().unwrap();
+        assert!(nu
+            .call_decl("does not exist", vec![], vec![], None)
+            .is_err());
+    }
+
+    #[test]
+    fn last_result_record() {
+        let mut nu = NuInstance::new().unwrap();
+        let start = chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:00+01:00").unwrap();
+        let error = ShellError::GenericError {
+            error: "boom".into(),
+            msg: "it broke".into(),
+            span: None,
+            help: None,
+            inner: vec![],
+        };
+        nu.set_last_result(
+            "foo",
+            &Err(error),
+            3,
+            start,
+            std::time::Duration::from_millis(1500),
+        );
+
+        let last = nu
+            .get_env_var("LAST_RESULT")
+            .unwrap()
+            .into_record()
+            .unwrap();
+        assert_eq!(
+            last.columns().map(String::as_str).collect::<Vec<_>>(),
+            [
+                "command",
+                "exit_code",
+                "errored",
+                "error",
+                "start",
+                "end",
+                "duration",
+                "value"
+            ]
+        );
+        assert_eq!(last.get("command").unwrap().as_str().unwrap(), "foo");
+        assert_eq!(last.get("exit_code").unwrap().as_int().unwrap(), 3);
+        assert!(last.get("errored").unwrap().as_bool().unwrap());
+        assert!(last
+            .get("error")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .contains("boom"));
+        assert_eq!(last.get("start").unwrap().as_date().unwrap(), start);
+        assert_eq!(
+            last.get("end").unwrap().as_date().unwrap(),
+            chrono::DateTime::parse_from_rfc3339("2024-01-01T00:00:01.5+01:00").unwrap()
+        );
+        assert_eq!(
+            last.get("duration").unwrap().as_duration().unwrap(),
+            1_500_000_000
+        );
+        assert!(last.get("value").unwrap().is_nothing());
+        assert_eq!(
+            nu.get_env_var("CMD_DURATION_MS").unwrap().as_str().unwrap(),
+            "1500"
+        );
+    }
+
+    #[test]
+    fn script_args() {
+        assert_eq!(script_arg("foo"), "foo");
+        assert_eq!(script_arg("-5"), "-5");
+        assert_eq!(script_arg("--verbose"), "--verbose");
+        assert_eq!(script_arg("--name=x y"), "--name=r#'x y'#");
+        assert_eq!(script_arg("(rm -rf ~)"), "r#'(rm -rf ~)'#");
+        assert_eq!(script_arg("$env.HOME"), "r#'$env.HOME'#");
+        assert_eq!(script_arg("a;b"), "r#'a;b'#");
+        assert_eq!(script_arg(""), "r#''#");
+    }
+}
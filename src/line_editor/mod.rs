@@ -0,0 +1,1328 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crossterm::{
+    event::{
+        self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers,
+    },
+    terminal,
+};
+use nu_protocol::Value;
+
+use crate::{ansi, commands::complete::Completion};
+
+mod vi;
+
+use vi::ViMode;
+
+const KILL_RING_SIZE: usize = 32;
+const MENU_SIZE: usize = 10;
+
+/// what the editor needs from the nu side
+pub trait EditorHost {
+    fn highlight(&mut self, text: &str) -> String;
+    /// `cursor` is a char-offset
+    fn complete(&mut self, text: &str, cursor: usize) -> Vec<Completion>;
+    fn is_complete(&mut self, text: &str) -> bool;
+    /// run a user-defined (closure) binding, errors are shown below the input
+    fn custom(&mut self, key: &str, text: &str, cursor: usize) -> Result<CustomEdit, String>;
+    /// edit the text in a external editor
+    fn open_editor(&mut self, text: &str) -> Result<String, String>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// submit (or insert a newline if the code is incomplete)
+    Submit,
+    InsertNewline,
+    Cancel,
+    DeleteOrExit,
+    MoveLeft,
+    MoveRight,
+    MoveWordLeft,
+    MoveWordRight,
+    MoveToLineStart,
+    MoveToLineEnd,
+    Backspace,
+    Delete,
+    KillToLineEnd,
+    KillToLineStart,
+    KillWordLeft,
+    KillWordRight,
+    Yank,
+    YankPop,
+    Undo,
+    /// previous line or (on the first line) previous history entry
+    Up,
+    /// next line or (on the last line) next history entry
+    Down,
+    HistoryPrevious,
+    HistoryNext,
+    /// incremental reverse history search: typing narrows it down, pressing it again finds
+    /// older matches, esc/cancel restores the line, other keys accept the match
+    HistorySearch,
+    Complete,
+    CompletePrevious,
+    CancelCompletion,
+    ClearScreen,
+    /// edit the input in `$env.config.buffer_editor`
+    OpenEditor,
+}
+
+impl Action {
+    const NAMES: &[(&str, Action)] = &[
+        ("submit", Action::Submit),
+        ("insert_newline", Action::InsertNewline),
+        ("cancel", Action::Cancel),
+        ("delete_or_exit", Action::DeleteOrExit),
+        ("move_left", Action::MoveLeft),
+        ("move_right", Action::MoveRight),
+        ("move_word_left", Action::MoveWordLeft),
+        ("move_word_right", Action::MoveWordRight),
+        ("move_to_line_start", Action::MoveToLineStart),
+        ("move_to_line_end", Action::MoveToLineEnd),
+        ("backspace", Action::Backspace),
+        ("delete", Action::Delete),
+        ("kill_to_line_end", Action::KillToLineEnd),
+        ("kill_to_line_start", Action::KillToLineStart),
+        ("kill_word_left", Action::KillWordLeft),
+        ("kill_word_right", Action::KillWordRight),
+        ("yank", Action::Yank),
+        ("yank_pop", Action::YankPop),
+        ("undo", Action::Undo),
+        ("up", Action::Up),
+        ("down", Action::Down),
+        ("history_previous", Action::HistoryPrevious),
+        ("history_next", Action::HistoryNext),
+        ("history_search", Action::HistorySearch),
+        ("complete", Action::Complete),
+        ("complete_previous", Action::CompletePrevious),
+        ("cancel_completion", Action::CancelCompletion),
+        ("clear_screen", Action::ClearScreen),
+        ("open_editor", Action::OpenEditor),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, action)| *action)
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, action)| *action == self)
+            .map(|(n, _)| *n)
+            .unwrap_or("unknown")
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::NAMES.iter().map(|(n, _)| *n)
+    }
+
+    fn is_kill(self) -> bool {
+        matches!(
+            self,
+            Action::KillToLineEnd
+                | Action::KillToLineStart
+                | Action::KillWordLeft
+                | Action::KillWordRight
+        )
+    }
+}
+
+pub fn default_keybindings() -> HashMap<String, Action> {
+    [
+        ("enter", Action::Submit),
+        ("alt+enter", Action::InsertNewline),
+        ("ctrl+c", Action::Cancel),
+        ("ctrl+d", Action::DeleteOrExit),
+        ("left", Action::MoveLeft),
+        ("ctrl+b", Action::MoveLeft),
+        ("right", Action::MoveRight),
+        ("ctrl+f", Action::MoveRight),
+        ("alt+left", Action::MoveWordLeft),
+        ("ctrl+left", Action::MoveWordLeft),
+        ("alt+b", Action::MoveWordLeft),
+        ("alt+right", Action::MoveWordRight),
+        ("ctrl+right", Action::MoveWordRight),
+        ("alt+f", Action::MoveWordRight),
+        ("home", Action::MoveToLineStart),
+        ("ctrl+a", Action::MoveToLineStart),
+        ("end", Action::MoveToLineEnd),
+        ("ctrl+e", Action::MoveToLineEnd),
+        ("backspace", Action::Backspace),
+        ("ctrl+h", Action::Backspace),
+        ("delete", Action::Delete),
+        ("ctrl+k", Action::KillToLineEnd),
+        ("ctrl+u", Action::KillToLineStart),
+        ("ctrl+w", Action::KillWordLeft),
+        ("alt+backspace", Action::KillWordLeft),
+        ("alt+d", Action::KillWordRight),
+        ("ctrl+y", Action::Yank),
+        ("alt+y", Action::YankPop),
+        ("ctrl+z", Action::Undo),
+        ("ctrl+_", Action::Undo),
+        ("up", Action::Up),
+        ("down", Action::Down),
+        ("ctrl+p", Action::HistoryPrevious),
+        ("ctrl+n", Action::HistoryNext),
+        ("ctrl+r", Action::HistorySearch),
+        ("tab", Action::Complete),
+        ("shift+tab", Action::CompletePrevious),
+        ("esc", Action::CancelCompletion),
+        ("ctrl+l", Action::ClearScreen),
+        ("ctrl+o", Action::OpenEditor),
+    ]
+    .into_iter()
+    .map(|(key, action)| (key.to_string(), action))
+    .collect()
+}
+
+/// name of a key-press like `ctrl+a`, `alt+backspace`, `shift+tab`, `enter`, or `A`
+/// (shift is part of the character for character keys)
+pub fn key_name(key: &KeyEvent) -> String {
+    let mut name = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        name.push_str("ctrl+");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        name.push_str("alt+");
+    }
+    let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+    let code = match key.code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => {
+            name.push(c);
+            return name;
+        }
+        KeyCode::BackTab => {
+            name.push_str("shift+tab");
+            return name;
+        }
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Home => "home".to_string(),
+        KeyCode::End => "end".to_string(),
+        KeyCode::PageUp => "pageup".to_string(),
+        KeyCode::PageDown => "pagedown".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Delete => "delete".to_string(),
+        KeyCode::Insert => "insert".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::F(n) => format!("f{n}"),
+        other => format!("{other:?}").to_lowercase(),
+    };
+    if shift {
+        name.push_str("shift+");
+    }
+    name.push_str(&code);
+    name
+}
+
+/// normalise a user-written key name (`Ctrl+A`, `control+alt+Enter`, `shift+x`) to the
+/// format of [`key_name`] (`None` for unknown modifiers)
+pub fn normalize_key_name(name: &str) -> Option<String> {
+    let (modifiers, key) = if name == "+" {
+        ("", "+")
+    } else if let Some(modifiers) = name.strip_suffix("++") {
+        (modifiers, "+")
+    } else {
+        name.rsplit_once('+').unwrap_or(("", name))
+    };
+    let (mut ctrl, mut alt, mut shift) = (false, false, false);
+    for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+        match modifier.to_lowercase().as_str() {
+            "ctrl" | "control" => ctrl = true,
+            "alt" | "meta" | "option" => alt = true,
+            "shift" => shift = true,
+            _ => return None,
+        }
+    }
+    let mut key = if key.chars().count() == 1 {
+        key.to_string()
+    } else {
+        key.to_lowercase()
+    };
+    match key.as_str() {
+        " " => key = "space".into(),
+        "escape" => key = "esc".into(),
+        "return" => key = "enter".into(),
+        "backtab" => {
+            key = "tab".into();
+            shift = true;
+        }
+        _ => {}
+    }
+    if shift && key.chars().count() == 1 {
+        key = key.to_uppercase();
+        shift = false;
+    }
+
+    let mut res = String::new();
+    if ctrl {
+        res.push_str("ctrl+");
+    }
+    if alt {
+        res.push_str("alt+");
+    }
+    if shift {
+        res.push_str("shift+");
+    }
+    res.push_str(&key);
+    Some(res)
+}
+
+/// [`key_name`] of a key event recorded by `input listen` (`None` for non-key events and
+/// keys without a name)
+pub fn key_name_from_value(value: &Value) -> Option<String> {
+    let Value::Record { val, .. } = value else {
+        return None;
+    };
+    let string = |key: &str| match val.get(key) {
+        Some(Value::String { val, .. }) => Some(val.as_str()),
+        _ => None,
+    };
+    if string("type")? != "key" {
+        return None;
+    }
+    let code = string("code")?;
+    let code = match string("key_type")? {
+        "char" => KeyCode::Char(code.chars().next()?),
+        "f" => KeyCode::F(code.parse().ok()?),
+        "other" => match code {
+            "backspace" => KeyCode::Backspace,
+            "enter" => KeyCode::Enter,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "esc" => KeyCode::Esc,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let mut modifiers = KeyModifiers::NONE;
+    if let Some(Value::List { vals, .. }) = val.get("modifiers") {
+        for modifier in vals.iter().filter_map(|m| m.as_str().ok()) {
+            modifiers |= match modifier {
+                "keymodifiers(control)" => KeyModifiers::CONTROL,
+                "keymodifiers(alt)" | "keymodifiers(meta)" => KeyModifiers::ALT,
+                "keymodifiers(shift)" => KeyModifiers::SHIFT,
+                _ => KeyModifiers::NONE,
+            };
+        }
+    }
+    Some(key_name(&KeyEvent::new(code, modifiers)))
+}
+
+/// what a key is bound to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Action(Action),
+    /// a user-defined binding (nu closure) run through [`EditorHost::custom`]
+    Custom(String),
+}
+
+/// result of a user-defined binding: replace the text / move the cursor, then run an action
+#[derive(Clone, Debug, Default)]
+pub struct CustomEdit {
+    pub text: Option<String>,
+    pub cursor: Option<usize>,
+    pub action: Option<Action>,
+}
+
+impl CustomEdit {
+    /// `null`, an action name, or a record with the optional keys `text`, `cursor`, and `action`
+    pub fn from_value(value: &Value) -> Result<Self, String> {
+        let action = |value: &Value| match value {
+            Value::Nothing { .. } => Ok(None),
+            Value::String { val, .. } => Action::from_name(val)
+                .map(Some)
+                .ok_or_else(|| format!("unknown editor action: {val}")),
+            other => Err(format!(
+                "expected an editor action name, got {}",
+                other.get_type()
+            )),
+        };
+        match value {
+            Value::Record { val, .. } => Ok(Self {
+                text: match val.get("text") {
+                    None | Some(Value::Nothing { .. }) => None,
+                    Some(Value::String { val, .. }) => Some(val.clone()),
+                    Some(other) => {
+                        return Err(format!(
+                            "expected text to be a string, got {}",
+                            other.get_type()
+                        ));
+                    }
+                },
+                cursor: match val.get("cursor") {
+                    None | Some(Value::Nothing { .. }) => None,
+                    Some(Value::Int { val, .. }) => Some((*val).max(0) as usize),
+                    Some(other) => {
+                        return Err(format!(
+                            "expected cursor to be a int, got {}",
+                            other.get_type()
+                        ));
+                    }
+                },
+                action: match val.get("action") {
+                    Some(value) => action(value)?,
+                    None => None,
+                },
+            }),
+            other => Ok(Self {
+                action: action(other)?,
+                ..Self::default()
+            }),
+        }
+    }
+}
+
+/// what `_heretic_nu_prompt` left in `$env._HERETIC_NU_PROMPT`
+#[derive(Clone, Debug)]
+pub struct PromptInfo {
+    pub left: String,
+    pub lines: usize,
+    pub left_width: usize,
+    pub columns: usize,
+    pub right: String,
+    pub right_width: usize,
+    pub multiline_indicator: String,
+    pub multiline_indicator_width: usize,
+    /// `emacs` or `vi` (`$env.config.edit_mode`)
+    pub edit_mode: String,
+    pub vi_insert: String,
+    pub vi_normal: String,
+    pub vi_visual: String,
+}
+
+impl Default for PromptInfo {
+    fn default() -> Self {
+        Self {
+            left: String::new(),
+            lines: 1,
+            left_width: 0,
+            columns: ansi::terminal_columns(),
+            right: String::new(),
+            right_width: 0,
+            multiline_indicator: String::new(),
+            multiline_indicator_width: 0,
+            edit_mode: "emacs".into(),
+            vi_insert: String::new(),
+            vi_normal: String::new(),
+            vi_visual: String::new(),
+        }
+    }
+}
+
+impl PromptInfo {
+    pub fn from_value(value: Option<&Value>) -> Self {
+        let mut res = Self::default();
+        let Some(Value::Record { val, .. }) = value else {
+            return res;
+        };
+        let string = |key: &str| match val.get(key) {
+            Some(Value::String { val, .. }) => Some(val.clone()),
+            _ => None,
+        };
+        let int = |key: &str| match val.get(key) {
+            Some(Value::Int { val, .. }) => Some((*val).max(0) as usize),
+            _ => None,
+        };
+        res.left = string("left").unwrap_or(res.left);
+        res.lines = int("lines").unwrap_or(res.lines);
+        res.left_width = int("left_width").unwrap_or(res.left_width);
+        res.columns = int("columns").unwrap_or(res.columns);
+        res.right = string("right").unwrap_or(res.right);
+        res.right_width = int("right_width").unwrap_or(res.right_width);
+        res.multiline_indicator = string("multiline_indicator").unwrap_or(res.multiline_indicator);
+        res.multiline_indicator_width =
+            int("multiline_indicator_width").unwrap_or(res.multiline_indicator_width);
+        res.edit_mode = string("edit_mode").unwrap_or(res.edit_mode);
+        res.vi_insert = string("vi_insert").unwrap_or(res.vi_insert);
+        res.vi_normal = string("vi_normal").unwrap_or(res.vi_normal);
+        res.vi_visual = string("vi_visual").unwrap_or_else(|| res.vi_normal.clone());
+        res
+    }
+}
+
+pub enum Outcome {
+    Submit(String),
+    Cancel,
+    Exit,
+}
+
+struct CompletionMenu {
+    base: Vec<char>,
+    items: Vec<Completion>,
+    selected: usize,
+}
+
+/// raw-mode for as long as it lives
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), EnableBracketedPaste)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), DisableBracketedPaste);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// state of the incremental reverse history search
+struct HistorySearch {
+    query: String,
+    /// history index of the current match
+    hit: Option<usize>,
+    /// no (further) entry contains the query
+    failing: bool,
+    /// text and cursor before the search (restored when it is cancelled)
+    base: (Vec<char>, usize),
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub struct LineEditor {
+    buffer: Vec<char>,
+    cursor: usize,
+    keybindings: HashMap<String, Binding>,
+    prompt: PromptInfo,
+    /// newest first, index 0 is the line currently being edited
+    history: Vec<String>,
+    history_idx: usize,
+    kill_ring: Vec<String>,
+    /// (start, length, kill-ring index) of the last yank
+    last_yank: Option<(usize, usize, usize)>,
+    undo_stack: Vec<(Vec<char>, usize)>,
+    last_action: Option<Action>,
+    typing: bool,
+    menu: Option<CompletionMenu>,
+    search: Option<HistorySearch>,
+    /// error of the last user-defined binding
+    message: Option<String>,
+    /// only set in vi mode
+    vi: Option<vi::ViState>,
+}
+
+impl LineEditor {
+    /// `history`: oldest first
+    pub fn new(
+        prompt: PromptInfo,
+        keybindings: HashMap<String, Binding>,
+        history: Vec<String>,
+    ) -> Self {
+        let mut history: Vec<String> = history.into_iter().rev().collect();
+        history.insert(0, String::new());
+        Self {
+            buffer: Vec::new(),
+            cursor: 0,
+            keybindings,
+            history,
+            history_idx: 0,
+            kill_ring: Vec::new(),
+            last_yank: None,
+            undo_stack: Vec::new(),
+            last_action: None,
+            typing: false,
+            menu: None,
+            search: None,
+            message: None,
+            vi: (prompt.edit_mode == "vi").then(vi::ViState::default),
+            prompt,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.buffer.iter().collect()
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.buffer = text.chars().collect();
+        self.cursor = self.buffer.len();
+    }
+
+    pub fn read_line(&mut self, host: &mut dyn EditorHost) -> io::Result<Outcome> {
+        let _raw_mode = RawMode::enable()?;
+        let mut out = io::stdout();
+        write!(out, "\x1b[s\x1b[0J")?;
+
+        loop {
+            self.render(&mut out, host)?;
+            match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => {
+                    if let Some(outcome) = self.handle_key(key, host, &mut out)? {
+                        self.menu = None;
+                        self.cursor = self.buffer.len();
+                        self.render(&mut out, host)?;
+                        return Ok(outcome);
+                    }
+                }
+                Event::Paste(text) => {
+                    self.menu = None;
+                    self.message = None;
+                    self.snapshot();
+                    self.insert_str(&text.replace("\r\n", "\n").replace('\r', "\n"));
+                    self.typing = false;
+                    self.last_action = None;
+                }
+                Event::Resize(columns, _rows) => {
+                    self.prompt.columns = columns as usize;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn handle_key(
+        &mut self,
+        key: KeyEvent,
+        host: &mut dyn EditorHost,
+        out: &mut impl Write,
+    ) -> io::Result<Option<Outcome>> {
+        self.message = None;
+        if self.search.is_some() && self.handle_search_key(&key) {
+            return Ok(None);
+        }
+        if let Some(outcome) = self.handle_vi_key(key, host)? {
+            return Ok(outcome);
+        }
+        let action = match self.keybindings.get(&key_name(&key)).cloned() {
+            Some(Binding::Action(action)) => action,
+            Some(Binding::Custom(name)) => {
+                self.menu = None;
+                let action = self.run_custom(&name, host);
+                self.typing = false;
+                self.last_action = None;
+                match action {
+                    Some(action) => action,
+                    None => return Ok(None),
+                }
+            }
+            None => {
+                self.menu = None;
+                if let KeyCode::Char(c) = key.code {
+                    if !key
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                    {
+                        // a run of typed characters is undone at once
+                        if !self.typing {
+                            self.snapshot();
+                        }
+                        self.insert_str(&c.to_string());
+                        self.typing = true;
+                        self.last_action = None;
+                    }
+                }
+                return Ok(None);
+            }
+        };
+        if !matches!(
+            action,
+            Action::Complete | Action::CompletePrevious | Action::CancelCompletion
+        ) {
+            self.menu = None;
+        }
+        let outcome = self.apply(action, host, out)?;
+        self.vi_clamp_cursor();
+        self.typing = false;
+        self.last_action = Some(action);
+        Ok(outcome)
+    }
+
+    pub fn apply(
+        &mut self,
+        action: Action,
+        host: &mut dyn EditorHost,
+        out: &mut impl Write,
+    ) -> io::Result<Option<Outcome>> {
+        match action {
+            Action::Submit => {
+                let text = self.text();
+                if host.is_complete(&text) {
+                    return Ok(Some(Outcome::Submit(text)));
+                }
+                self.snapshot();
+                self.insert_str("\n");
+            }
+            Action::InsertNewline => {
+                self.snapshot();
+                self.insert_str("\n");
+            }
+            Action::Cancel => return Ok(Some(Outcome::Cancel)),
+            Action::DeleteOrExit => {
+                if self.buffer.is_empty() {
+                    return Ok(Some(Outcome::Exit));
+                }
+                if self.cursor < self.buffer.len() {
+                    self.snapshot();
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Action::MoveLeft => self.cursor = self.cursor.saturating_sub(1),
+            Action::MoveRight => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Action::MoveWordLeft => self.cursor = self.word_left(),
+            Action::MoveWordRight => self.cursor = self.word_right(),
+            Action::MoveToLineStart => self.cursor = self.line_start(),
+            Action::MoveToLineEnd => self.cursor = self.line_end(),
+            Action::Backspace => {
+                if self.cursor > 0 {
+                    if self.last_action != Some(Action::Backspace) {
+                        self.snapshot();
+                    }
+                    self.cursor -= 1;
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Action::Delete => {
+                if self.cursor < self.buffer.len() {
+                    if self.last_action != Some(Action::Delete) {
+                        self.snapshot();
+                    }
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Action::KillToLineEnd => self.kill(self.cursor, self.line_end()),
+            Action::KillToLineStart => self.kill(self.line_start(), self.cursor),
+            Action::KillWordLeft => self.kill(self.word_left(), self.cursor),
+            Action::KillWordRight => self.kill(self.cursor, self.word_right()),
+            Action::Yank => {
+                if let Some(text) = self.kill_ring.last().cloned() {
+                    self.snapshot();
+                    self.last_yank =
+                        Some((self.cursor, text.chars().count(), self.kill_ring.len() - 1));
+                    self.insert_str(&text);
+                }
+            }
+            Action::YankPop => {
+                if let (Some(Action::Yank | Action::YankPop), Some((start, len, idx))) =
+                    (self.last_action, self.last_yank)
+                {
+                    let idx = (idx + self.kill_ring.len() - 1) % self.kill_ring.len();
+                    let text = self.kill_ring[idx].clone();
+                    self.buffer.drain(start..start + len);
+                    self.cursor = start;
+                    self.insert_str(&text);
+                    self.last_yank = Some((start, text.chars().count(), idx));
+                }
+            }
+            Action::Undo => {
+                while let Some((buffer, cursor)) = self.undo_stack.pop() {
+                    if buffer != self.buffer {
+                        self.buffer = buffer;
+                        self.cursor = cursor;
+                        break;
+                    }
+                }
+            }
+            Action::Up => {
+                if self.row() > 0 {
+                    self.move_vertical(-1);
+                } else {
+                    self.history_move(self.history_idx + 1);
+                }
+            }
+            Action::Down => {
+                if self.row() < self.text().matches('\n').count() {
+                    self.move_vertical(1);
+                } else if self.history_idx > 0 {
+                    self.history_move(self.history_idx - 1);
+                }
+            }
+            Action::HistoryPrevious => self.history_move(self.history_idx + 1),
+            Action::HistoryNext => {
+                if self.history_idx > 0 {
+                    self.history_move(self.history_idx - 1);
+                }
+            }
+            Action::HistorySearch => match &self.search {
+                Some(search) => self.search_history(search.hit.map_or(1, |hit| hit + 1)),
+                None => {
+                    self.snapshot();
+                    self.search = Some(HistorySearch {
+                        query: String::new(),
+                        hit: None,
+                        failing: false,
+                        base: (self.buffer.clone(), self.cursor),
+                    });
+                }
+            },
+            Action::Complete => self.complete(host, false),
+            Action::CompletePrevious => self.complete(host, true),
+            Action::CancelCompletion => {
+                if let Some(menu) = self.menu.take() {
+                    self.buffer = menu.base;
+                    self.cursor = self.cursor.min(self.buffer.len());
+                }
+            }
+            Action::ClearScreen => {
+                write!(
+                    out,
+                    "\x1b[2J\x1b[H{}\x1b[s",
+                    self.prompt.left.replace('\n', "\r\n")
+                )?;
+            }
+            Action::OpenEditor => {
+                // the editor needs a normal terminal
+                crossterm::execute!(io::stdout(), DisableBracketedPaste)?;
+                terminal::disable_raw_mode()?;
+                let res = host.open_editor(&self.text());
+                terminal::enable_raw_mode()?;
+                crossterm::execute!(io::stdout(), EnableBracketedPaste)?;
+                match res {
+                    Ok(text) => {
+                        self.snapshot();
+                        self.set_text(&text);
+                    }
+                    Err(message) => self.message = Some(message),
+                }
+                // the editor may have moved the cursor, start over below it
+                write!(out, "\r\n{}\x1b[s", self.prompt.left.replace('\n', "\r\n"))?;
+            }
+        }
+        Ok(None)
+    }
+
+    /// a key-press during the history search, `false` if it ended the search and still has to
+    /// be handled (the match stays as the text)
+    fn handle_search_key(&mut self, key: &KeyEvent) -> bool {
+        let action = match self.keybindings.get(&key_name(key)) {
+            Some(Binding::Action(action)) => Some(*action),
+            _ => None,
+        };
+        let plain = !key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        let Some(search) = self.search.as_mut() else {
+            return false;
+        };
+        match (key.code, action) {
+            // the search itself
+            (_, Some(Action::HistorySearch)) => return false,
+            (KeyCode::Esc, _) | (_, Some(Action::Cancel)) => {
+                (self.buffer, self.cursor) = search.base.clone();
+                self.search = None;
+            }
+            (KeyCode::Backspace, _) => {
+                search.query.pop();
+                self.search_history(1);
+            }
+            (KeyCode::Char(c), None) if plain => {
+                search.query.push(c);
+                let from = search.hit.unwrap_or(1);
+                self.search_history(from);
+            }
+            _ => {
+                self.search = None;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// show the newest history entry (starting at index `from`) containing the search query
+    fn search_history(&mut self, from: usize) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let hit = self
+            .history
+            .iter()
+            .enumerate()
+            .skip(from.max(1))
+            .find_map(|(idx, entry)| Some((idx, entry.find(&search.query)?)));
+        match hit {
+            Some((idx, byte_offset)) => {
+                let entry = &self.history[idx];
+                search.hit = Some(idx);
+                search.failing = false;
+                self.cursor = entry[..byte_offset].chars().count();
+                self.buffer = entry.chars().collect();
+            }
+            None => search.failing = true,
+        }
+    }
+
+    fn run_custom(&mut self, name: &str, host: &mut dyn EditorHost) -> Option<Action> {
+        match host.custom(name, &self.text(), self.cursor) {
+            Ok(edit) => {
+                if let Some(text) = edit.text {
+                    self.snapshot();
+                    self.set_text(&text);
+                }
+                if let Some(cursor) = edit.cursor {
+                    self.cursor = cursor.min(self.buffer.len());
+                }
+                edit.action
+            }
+            Err(message) => {
+                self.message = Some(message);
+                None
+            }
+        }
+    }
+
+    fn snapshot(&mut self) {
+        if self.undo_stack.last().map(|(buffer, _)| buffer) != Some(&self.buffer) {
+            self.undo_stack.push((self.buffer.clone(), self.cursor));
+        }
+    }
+
+    fn insert_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.buffer.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    fn kill(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let backwards = end == self.cursor;
+        let merge = self.last_action.is_some_and(Action::is_kill);
+        self.snapshot();
+        let killed: String = self.buffer.drain(start..end).collect();
+        self.cursor = start;
+        match self.kill_ring.last_mut() {
+            // consecutive kills end up as one kill-ring entry
+            Some(last) if merge => {
+                if backwards {
+                    last.insert_str(0, &killed);
+                } else {
+                    last.push_str(&killed);
+                }
+            }
+            _ => self.push_kill(killed),
+        }
+    }
+
+    /// add a new kill-ring entry (dropping the oldest one if the ring is full)
+    fn push_kill(&mut self, text: String) {
+        self.kill_ring.push(text);
+        if self.kill_ring.len() > KILL_RING_SIZE {
+            self.kill_ring.remove(0);
+        }
+    }
+
+    fn word_left(&self) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && !is_word_char(self.buffer[i - 1]) {
+            i -= 1;
+        }
+        while i > 0 && is_word_char(self.buffer[i - 1]) {
+            i -= 1;
+        }
+        i
+    }
+
+    fn word_right(&self) -> usize {
+        let mut i = self.cursor;
+        while i < self.buffer.len() && !is_word_char(self.buffer[i]) {
+            i += 1;
+        }
+        while i < self.buffer.len() && is_word_char(self.buffer[i]) {
+            i += 1;
+        }
+        i
+    }
+
+    fn line_start(&self) -> usize {
+        self.buffer[..self.cursor]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    }
+
+    fn line_end(&self) -> usize {
+        self.buffer[self.cursor..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map(|i| self.cursor + i)
+            .unwrap_or(self.buffer.len())
+    }
+
+    fn row(&self) -> usize {
+        self.buffer[..self.cursor]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count()
+    }
+
+    fn move_vertical(&mut self, direction: isize) {
+        let column = self.cursor - self.line_start();
+        if direction < 0 {
+            self.cursor = self.line_start() - 1;
+        } else {
+            self.cursor = self.line_end() + 1;
+        }
+        let start = self.line_start();
+        self.cursor = (start + column).min(self.line_end());
+    }
+
+    fn history_move(&mut self, idx: usize) {
+        if idx >= self.history.len() {
+            return;
+        }
+        self.history[self.history_idx] = self.text();
+        self.history_idx = idx;
+        let text = self.history[idx].clone();
+        self.set_text(&text);
+    }
+
+    fn complete(&mut self, host: &mut dyn EditorHost, backwards: bool) {
+        if self.menu.is_none() {
+            let items = host.complete(&self.text(), self.cursor);
+            match items.len() {
+                0 => return,
+                1 => {
+                    self.snapshot();
+                    let base = self.buffer.clone();
+                    self.apply_completion(&base, &items[0], true);
+                    return;
+                }
+                _ => {
+                    self.snapshot();
+                    self.menu = Some(CompletionMenu {
+                        base: self.buffer.clone(),
+                        selected: if backwards { 0 } else { items.len() - 1 },
+                        items,
+                    });
+                }
+            }
+        }
+        let Some(mut menu) = self.menu.take() else {
+            return;
+        };
+        menu.selected = if backwards {
+            (menu.selected + menu.items.len() - 1) % menu.items.len()
+        } else {
+            (menu.selected + 1) % menu.items.len()
+        };
+        self.apply_completion(&menu.base, &menu.items[menu.selected], false);
+        self.menu = Some(menu);
+    }
+
+    fn apply_completion(&mut self, base: &[char], completion: &Completion, single: bool) {
+        let start = completion.start.min(base.len());
+        let end = completion.end.clamp(start, base.len());
+        let mut buffer: Vec<char> = base[..start].to_vec();
+        buffer.extend(completion.value.chars());
+        if single && completion.append_whitespace {
+            buffer.push(' ');
+        }
+        self.cursor = buffer.len();
+        buffer.extend_from_slice(&base[end..]);
+        self.buffer = buffer;
+    }
+
+    fn menu_hint(&self) -> String {
+        let Some(menu) = &self.menu else {
+            return String::new();
+        };
+        let items: Vec<String> = menu
+            .items
+            .iter()
+            .enumerate()
+            .skip(menu.selected.saturating_sub(MENU_SIZE / 2))
+            .take(MENU_SIZE)
+            .map(|(idx, item)| {
+                if idx == menu.selected {
+                    format!("\x1b[7m{}\x1b[27m", item.value)
+                } else {
+                    item.value.clone()
+                }
+            })
+            .collect();
+        format!("  {}", items.join(" "))
+    }
+
+    fn render(&self, out: &mut impl Write, host: &mut dyn EditorHost) -> io::Result<()> {
+        let text = self.text();
+        let lines: Vec<&str> = text.split('\n').collect();
+        let rows = lines.len() - 1;
+        let before: String = self.buffer[..self.cursor].iter().collect();
+        let before: Vec<&str> = before.split('\n').collect();
+        let row = before.len() - 1;
+        let indicator: &str = match self.vi_mode() {
+            None => "",
+            Some(ViMode::Insert) => &self.prompt.vi_insert,
+            Some(ViMode::Normal) => &self.prompt.vi_normal,
+            Some(ViMode::Visual) => &self.prompt.vi_visual,
+        };
+        let indicator_width = ansi::display_width(indicator);
+        let col = ansi::display_width(before[row])
+            + if row == 0 {
+                indicator_width
+            } else {
+                self.prompt.multiline_indicator_width
+            };
+        let hint = match (&self.message, &self.search) {
+            (Some(message), _) => format!("  {message}"),
+            (None, Some(search)) => format!(
+                "  ({}reverse-i-search)`{}'",
+                if search.failing { "failing " } else { "" },
+                search.query
+            ),
+            (None, None) => self.menu_hint(),
+        };
+
+        let mut frame = String::from("\x1b[u");
+        if rows > 0 {
+            // make room for all rows (scrolling the screen if needed) and re-anchor the start there
+            frame.push_str(&"\x1bD".repeat(rows));
+            frame.push_str(&format!("\x1b[{rows}A\x1b[s"));
+        }
+        frame.push_str("\x1b[0J");
+        frame.push_str(indicator);
+        let body = match self.vi_selection() {
+            // the selection is shown instead of the highlighting
+            Some((start, end)) => format!(
+                "{}\x1b[7m{}\x1b[27m{}",
+                self.buffer[..start].iter().collect::<String>(),
+                self.buffer[start..end].iter().collect::<String>(),
+                self.buffer[end..].iter().collect::<String>(),
+            ),
+            None => host.highlight(&text),
+        };
+        frame.push_str(&body.replace('\n', &format!("\r\n{}", self.prompt.multiline_indicator)));
+        frame.push_str(&format!(" \x1b[2m{hint}\x1b[0m"));
+        // the right prompt is hidden as soon as the input (of the first line) would reach it
+        let first_line_width = self.prompt.left_width
+            + indicator_width
+            + ansi::display_width(lines[0])
+            + 1
+            + if rows == 0 {
+                ansi::display_width(&hint)
+            } else {
+                0
+            };
+        if !self.prompt.right.is_empty()
+            && first_line_width + self.prompt.right_width < self.prompt.columns
+        {
+            frame.push_str(&format!(
+                "\x1b[u\x1b[{}G{}",
+                self.prompt.columns - self.prompt.right_width + 1,
+                self.prompt.right
+            ));
+        }
+        frame.push_str("\x1b[u");
+        if row > 0 {
+            frame.push_str(&format!("\x1b[{row}B\r"));
+        }
+        if col > 0 {
+            frame.push_str(&format!("\x1b[{col}C"));
+        }
+        out.write_all(frame.as_bytes())?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestHost;
+
+    impl EditorHost for TestHost {
+        fn highlight(&mut self, text: &str) -> String {
+            text.to_string()
+        }
+
+        fn complete(&mut self, _text: &str, _cursor: usize) -> Vec<Completion> {
+            Vec::new()
+        }
+
+        fn is_complete(&mut self, _text: &str) -> bool {
+            true
+        }
+
+        fn custom(
+            &mut self,
+            _key: &str,
+            _text: &str,
+            _cursor: usize,
+        ) -> Result<CustomEdit, String> {
+            Ok(CustomEdit::default())
+        }
+
+        fn open_editor(&mut self, text: &str) -> Result<String, String> {
+            Ok(text.to_string())
+        }
+    }
+
+    fn editor(text: &str, cursor: usize) -> LineEditor {
+        let mut editor = LineEditor::new(PromptInfo::default(), HashMap::new(), Vec::new());
+        editor.set_text(text);
+        editor.cursor = cursor;
+        editor
+    }
+
+    /// like a key-press bound to the action (without the vi handling)
+    fn run(editor: &mut LineEditor, actions: &[Action]) {
+        for action in actions {
+            editor
+                .apply(*action, &mut TestHost, &mut Vec::new())
+                .unwrap();
+            editor.typing = false;
+            editor.last_action = Some(*action);
+        }
+    }
+
+    #[test]
+    fn word_motions() {
+        let mut e = editor("ls  foo_bar/baz", 0);
+        run(&mut e, &[Action::MoveWordRight]);
+        assert_eq!(e.cursor, 2);
+        run(&mut e, &[Action::MoveWordRight]);
+        assert_eq!(e.cursor, 11);
+        run(&mut e, &[Action::MoveWordRight, Action::MoveWordRight]);
+        assert_eq!(e.cursor, 15);
+        run(&mut e, &[Action::MoveWordLeft]);
+        assert_eq!(e.cursor, 12);
+        run(&mut e, &[Action::MoveWordLeft]);
+        assert_eq!(e.cursor, 4);
+        run(&mut e, &[Action::MoveWordLeft, Action::MoveWordLeft]);
+        assert_eq!(e.cursor, 0);
+    }
+
+    #[test]
+    fn consecutive_kills_are_one_entry() {
+        let mut e = editor("echo foo bar", 12);
+        run(&mut e, &[Action::KillWordLeft, Action::KillWordLeft]);
+        assert_eq!(e.text(), "echo ");
+        assert_eq!(e.kill_ring, vec!["foo bar".to_string()]);
+
+        run(&mut e, &[Action::MoveToLineStart, Action::KillToLineEnd]);
+        assert_eq!(e.text(), "");
+        assert_eq!(
+            e.kill_ring,
+            vec!["foo bar".to_string(), "echo ".to_string()]
+        );
+    }
+
+    #[test]
+    fn yank_pop_cycles_the_kill_ring() {
+        let mut e = editor("a b", 3);
+        run(
+            &mut e,
+            &[Action::KillWordLeft, Action::MoveLeft, Action::KillWordLeft],
+        );
+        assert_eq!(e.text(), " ");
+        run(&mut e, &[Action::Yank]);
+        assert_eq!(e.text(), "a ");
+        run(&mut e, &[Action::YankPop]);
+        assert_eq!(e.text(), "b ");
+        run(&mut e, &[Action::YankPop]);
+        assert_eq!(e.text(), "a ");
+    }
+
+    #[test]
+    fn kill_ring_is_capped() {
+        let mut e = editor("", 0);
+        for i in 0..KILL_RING_SIZE + 5 {
+            e.insert_str(&format!("{i} "));
+            e.last_action = None;
+            run(&mut e, &[Action::KillToLineStart]);
+        }
+        assert_eq!(e.kill_ring.len(), KILL_RING_SIZE);
+        assert_eq!(e.kill_ring.first().map(String::as_str), Some("5 "));
+    }
+
+    #[test]
+    fn incremental_history_search() {
+        let history = ["ls foo", "echo bar", "ls baz"].map(String::from).to_vec();
+        let mut e = LineEditor::new(PromptInfo::default(), HashMap::new(), history);
+        e.set_text("typed");
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        run(&mut e, &[Action::HistorySearch]);
+        assert!(e.handle_search_key(&key('l')));
+        assert_eq!(e.text(), "ls baz");
+        assert!(e.handle_search_key(&key('s')));
+        assert_eq!((e.text().as_str(), e.cursor), ("ls baz", 0));
+        run(&mut e, &[Action::HistorySearch]);
+        assert_eq!(e.text(), "ls foo");
+        run(&mut e, &[Action::HistorySearch]);
+        assert!(e.search.as_ref().is_some_and(|search| search.failing));
+        assert_eq!(e.text(), "ls foo");
+
+        assert!(e.handle_search_key(&KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)));
+        assert_eq!(e.text(), "typed");
+        assert!(e.search.is_none());
+
+        run(&mut e, &[Action::HistorySearch]);
+        assert!(e.handle_search_key(&key('b')));
+        assert_eq!((e.text().as_str(), e.cursor), ("ls baz", 3));
+        assert!(e.handle_search_key(&key('a')));
+        assert!(e.handle_search_key(&key('r')));
+        assert_eq!(e.text(), "echo bar");
+        // other keys accept the match and are handled normally
+        assert!(!e.handle_search_key(&KeyEvent::new(KeyCode::Left, KeyModifiers::NONE)));
+        assert!(e.search.is_none());
+        assert_eq!(e.text(), "echo bar");
+    }
+
+    #[test]
+    fn undo() {
+        let mut e = editor("echo foo", 8);
+        run(&mut e, &[Action::Backspace, Action::Backspace]);
+        assert_eq!(e.text(), "echo f");
+        run(&mut e, &[Action::KillToLineStart]);
+        assert_eq!(e.text(), "");
+        // a run of backspaces is undone at once
+        run(&mut e, &[Action::Undo]);
+        assert_eq!(e.text(), "echo f");
+        run(&mut e, &[Action::Undo]);
+        assert_eq!(e.text(), "echo foo");
+        assert_eq!(e.cursor, 8);
+        run(&mut e, &[Action::Undo]);
+        assert_eq!(e.text(), "echo foo");
+    }
+
+    #[test]
+    fn normalize_key_names() {
+        assert_eq!(normalize_key_name("Ctrl+A").as_deref(), Some("ctrl+A"));
+        assert_eq!(normalize_key_name("ctrl+a").as_deref(), Some("ctrl+a"));
+        assert_eq!(
+            normalize_key_name("control+alt+Enter").as_deref(),
+            Some("ctrl+alt+enter")
+        );
+        assert_eq!(normalize_key_name("shift+x").as_deref(), Some("X"));
+        assert_eq!(normalize_key_name("meta+b").as_deref(), Some("alt+b"));
+        assert_eq!(normalize_key_name("BackTab").as_deref(), Some("shift+tab"));
+        assert_eq!(normalize_key_name("escape").as_deref(), Some("esc"));
+        assert_eq!(normalize_key_name("ctrl+ ").as_deref(), Some("ctrl+space"));
+        assert_eq!(normalize_key_name("+").as_deref(), Some("+"));
+        assert_eq!(normalize_key_name("ctrl++").as_deref(), Some("ctrl++"));
+        assert_eq!(normalize_key_name("hyper+a"), None);
+    }
+}
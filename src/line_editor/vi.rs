@@ -0,0 +1,671 @@
+//! vi editing mode (`$env.config.edit_mode = 'vi'`) of the [`LineEditor`]
+
+use std::io;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::{is_word_char, Action, EditorHost, LineEditor, Outcome};
+
+/// counts above this are clamped (`99999999x` should not hang the shell)
+const MAX_COUNT: usize = 9999;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ViMode {
+    #[default]
+    Insert,
+    Normal,
+    Visual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+impl Operator {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            _ => None,
+        }
+    }
+}
+
+/// a key waiting for the character it applies to (`f<char>`, `r<char>`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pending {
+    Find(char),
+    Replace,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Motion {
+    target: usize,
+    /// the character at the target is part of the range (`e`, `$`, `f`, `t`)
+    inclusive: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ViState {
+    pub mode: ViMode,
+    count: Option<usize>,
+    /// operator waiting for a motion and the count typed before it
+    operator: Option<(Operator, usize)>,
+    pending: Option<Pending>,
+    /// (`f`/`F`/`t`/`T`, character) for `;` and `,`
+    last_find: Option<(char, char)>,
+    visual_anchor: usize,
+    register: String,
+    linewise: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
+/// `big`: WORD (anything but whitespace) instead of word
+fn char_class(c: char, big: bool) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Space
+    } else if big || is_word_char(c) {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+fn next_word_start(buffer: &[char], mut i: usize, big: bool) -> usize {
+    let len = buffer.len();
+    if i >= len {
+        return len;
+    }
+    let class = char_class(buffer[i], big);
+    if class != CharClass::Space {
+        while i < len && char_class(buffer[i], big) == class {
+            i += 1;
+        }
+    }
+    while i < len && char_class(buffer[i], big) == CharClass::Space {
+        i += 1;
+    }
+    i
+}
+
+fn word_end(buffer: &[char], i: usize, big: bool) -> usize {
+    let len = buffer.len();
+    let mut i = i + 1;
+    while i < len && char_class(buffer[i], big) == CharClass::Space {
+        i += 1;
+    }
+    if i >= len {
+        return len.saturating_sub(1);
+    }
+    let class = char_class(buffer[i], big);
+    while i + 1 < len && char_class(buffer[i + 1], big) == class {
+        i += 1;
+    }
+    i
+}
+
+fn prev_word_start(buffer: &[char], mut i: usize, big: bool) -> usize {
+    while i > 0 && char_class(buffer[i - 1], big) == CharClass::Space {
+        i -= 1;
+    }
+    if i == 0 {
+        return 0;
+    }
+    let class = char_class(buffer[i - 1], big);
+    while i > 0 && char_class(buffer[i - 1], big) == class {
+        i -= 1;
+    }
+    i
+}
+
+impl LineEditor {
+    fn vi_mut(&mut self) -> &mut ViState {
+        self.vi.get_or_insert_with(ViState::default)
+    }
+
+    pub(super) fn vi_mode(&self) -> Option<ViMode> {
+        self.vi.as_ref().map(|vi| vi.mode)
+    }
+
+    /// `start..end` of the visual selection
+    pub(super) fn vi_selection(&self) -> Option<(usize, usize)> {
+        let vi = self.vi.as_ref()?;
+        if vi.mode != ViMode::Visual {
+            return None;
+        }
+        let start = vi.visual_anchor.min(self.cursor);
+        let end = (vi.visual_anchor.max(self.cursor) + 1).min(self.buffer.len());
+        Some((start, end))
+    }
+
+    fn set_vi_mode(&mut self, mode: ViMode) {
+        let vi = self.vi_mut();
+        let from = vi.mode;
+        vi.mode = mode;
+        vi.count = None;
+        vi.operator = None;
+        vi.pending = None;
+        match mode {
+            ViMode::Normal if from == ViMode::Insert => {
+                if self.cursor > self.line_start() {
+                    self.cursor -= 1;
+                }
+            }
+            ViMode::Visual => self.vi_mut().visual_anchor = self.cursor,
+            _ => {}
+        }
+        if mode == ViMode::Insert {
+            self.typing = false;
+        }
+    }
+
+    /// in normal- and visual-mode the cursor is on a character, not behind the line
+    pub(super) fn vi_clamp_cursor(&mut self) {
+        if matches!(self.vi_mode(), Some(ViMode::Normal | ViMode::Visual))
+            && self.cursor == self.line_end()
+            && self.cursor > self.line_start()
+        {
+            self.cursor -= 1;
+        }
+    }
+
+    /// `None` if the key is not handled by vi (and should go through the keybindings)
+    pub(super) fn handle_vi_key(
+        &mut self,
+        key: KeyEvent,
+        host: &mut dyn EditorHost,
+    ) -> io::Result<Option<Option<Outcome>>> {
+        let Some(mode) = self.vi_mode() else {
+            return Ok(None);
+        };
+        let plain = !key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match (mode, key.code) {
+            (ViMode::Insert, KeyCode::Esc) if plain && self.menu.is_none() => {
+                self.set_vi_mode(ViMode::Normal);
+                Ok(Some(None))
+            }
+            (ViMode::Insert, _) => Ok(None),
+            (_, KeyCode::Esc) if plain => {
+                self.menu = None;
+                self.set_vi_mode(ViMode::Normal);
+                self.vi_clamp_cursor();
+                Ok(Some(None))
+            }
+            (_, KeyCode::Char(c)) if plain => {
+                self.menu = None;
+                self.vi_char(c, host)?;
+                self.typing = false;
+                self.last_action = None;
+                self.vi_clamp_cursor();
+                Ok(Some(None))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// count of the current command (including the count typed before the operator)
+    fn take_count(&mut self) -> usize {
+        let vi = self.vi_mut();
+        let count = vi.count.take().unwrap_or(1);
+        let operator_count = vi.operator.map(|(_, count)| count).unwrap_or(1);
+        (count * operator_count).min(MAX_COUNT)
+    }
+
+    fn vi_char(&mut self, c: char, host: &mut dyn EditorHost) -> io::Result<()> {
+        let mode = self.vi_mode().unwrap_or_default();
+        if let Some(pending) = self.vi_mut().pending.take() {
+            let count = self.take_count();
+            match pending {
+                Pending::Find(kind) => {
+                    self.vi_mut().last_find = Some((kind, c));
+                    match self.find_motion(kind, c, count, false) {
+                        Some(motion) => self.vi_motion(motion),
+                        None => self.vi_mut().operator = None,
+                    }
+                }
+                Pending::Replace => self.vi_replace(c, count),
+            }
+            return Ok(());
+        }
+
+        let vi = self.vi_mut();
+        if c.is_ascii_digit() && (c != '0' || vi.count.is_some()) {
+            let digit = c.to_digit(10).unwrap_or(0) as usize;
+            vi.count = Some((vi.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+            return Ok(());
+        }
+        if matches!(c, 'f' | 'F' | 't' | 'T') {
+            vi.pending = Some(Pending::Find(c));
+            return Ok(());
+        }
+
+        let operator = vi.operator.map(|(operator, _)| operator);
+        let count = self.take_count();
+        if let Some(motion) = self.motion(c, count, operator) {
+            self.vi_motion(motion);
+            return Ok(());
+        }
+
+        if let Some(new_operator) = Operator::from_char(c) {
+            if mode == ViMode::Visual {
+                if let Some((start, end)) = self.vi_selection() {
+                    self.vi_apply(new_operator, start, end, false);
+                }
+                if self.vi_mode() == Some(ViMode::Visual) {
+                    self.set_vi_mode(ViMode::Normal);
+                }
+            } else if operator == Some(new_operator) {
+                // `dd`, `cc`, `yy`
+                self.vi_mut().operator = None;
+                self.vi_lines(new_operator, count);
+            } else {
+                self.vi_mut().operator = Some((new_operator, count));
+            }
+            return Ok(());
+        }
+        if self.vi_mut().operator.take().is_some() {
+            // unknown motion
+            return Ok(());
+        }
+
+        let line_start = self.line_start();
+        let line_end = self.line_end();
+        match c {
+            'i' => self.set_vi_mode(ViMode::Insert),
+            'a' => {
+                self.cursor = (self.cursor + 1).min(line_end);
+                self.set_vi_mode(ViMode::Insert);
+            }
+            'I' => {
+                self.cursor = self.first_non_blank();
+                self.set_vi_mode(ViMode::Insert);
+            }
+            'A' => {
+                self.cursor = line_end;
+                self.set_vi_mode(ViMode::Insert);
+            }
+            'o' | 'O' => {
+                self.snapshot();
+                self.cursor = if c == 'o' { line_end } else { line_start };
+                self.insert_str("\n");
+                if c == 'O' {
+                    self.cursor -= 1;
+                }
+                self.set_vi_mode(ViMode::Insert);
+            }
+            'x' | 'X' | 's' | 'D' | 'C' | 'S' if mode == ViMode::Normal => {
+                let (operator, motion) = match c {
+                    'x' => (Operator::Delete, 'l'),
+                    'X' => (Operator::Delete, 'h'),
+                    's' => (Operator::Change, 'l'),
+                    'D' => (Operator::Delete, '$'),
+                    'C' => (Operator::Change, '$'),
+                    _ => (Operator::Change, 'S'),
+                };
+                if motion == 'S' {
+                    self.vi_lines(operator, count);
+                } else if let Some(motion) = self.motion(motion, count, Some(operator)) {
+                    self.vi_mut().operator = Some((operator, 1));
+                    self.vi_motion(motion);
+                }
+            }
+            'x' | 'X' => {
+                if let Some((start, end)) = self.vi_selection() {
+                    self.vi_apply(Operator::Delete, start, end, false);
+                }
+                self.set_vi_mode(ViMode::Normal);
+            }
+            'r' if mode == ViMode::Normal => {
+                // the count is kept for the replacement character
+                self.vi_mut().count = Some(count);
+                self.vi_mut().pending = Some(Pending::Replace);
+            }
+            'p' | 'P' => self.vi_put(c == 'p', count),
+            '~' => self.vi_toggle_case(count),
+            'u' => {
+                for _ in 0..count {
+                    self.apply(Action::Undo, host, &mut io::sink())?;
+                }
+            }
+            'j' | 'k' => {
+                let action = if c == 'j' { Action::Down } else { Action::Up };
+                for _ in 0..count {
+                    self.apply(action, host, &mut io::sink())?;
+                }
+            }
+            'v' => self.set_vi_mode(if mode == ViMode::Visual {
+                ViMode::Normal
+            } else {
+                ViMode::Visual
+            }),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn first_non_blank(&self) -> usize {
+        let mut i = self.line_start();
+        let end = self.line_end();
+        while i < end && self.buffer[i].is_whitespace() {
+            i += 1;
+        }
+        i
+    }
+
+    fn motion(&self, c: char, count: usize, operator: Option<Operator>) -> Option<Motion> {
+        let exclusive = |target| {
+            Some(Motion {
+                target,
+                inclusive: false,
+            })
+        };
+        let buffer = &self.buffer;
+        let line_start = self.line_start();
+        let line_end = self.line_end();
+        match c {
+            'h' => exclusive(self.cursor.saturating_sub(count).max(line_start)),
+            'l' => exclusive((self.cursor + count).min(line_end)),
+            '0' => exclusive(line_start),
+            '^' => exclusive(self.first_non_blank()),
+            '$' => Some(Motion {
+                target: line_end.saturating_sub(1).max(line_start),
+                inclusive: line_end > line_start,
+            }),
+            // `cw` changes to the end of the word (like `ce`)
+            'w' | 'W'
+                if operator == Some(Operator::Change)
+                    && buffer.get(self.cursor).is_some_and(|c| !c.is_whitespace()) =>
+            {
+                let big = c == 'W';
+                let class = char_class(buffer[self.cursor], big);
+                let mut target = self.cursor;
+                while target + 1 < buffer.len() && char_class(buffer[target + 1], big) == class {
+                    target += 1;
+                }
+                for _ in 1..count {
+                    target = word_end(buffer, target, big);
+                }
+                Some(Motion {
+                    target,
+                    inclusive: true,
+                })
+            }
+            'w' | 'W' => {
+                let mut target = self.cursor;
+                for _ in 0..count {
+                    target = next_word_start(buffer, target, c == 'W');
+                }
+                exclusive(target)
+            }
+            'b' | 'B' => {
+                let mut target = self.cursor;
+                for _ in 0..count {
+                    target = prev_word_start(buffer, target, c == 'B');
+                }
+                exclusive(target)
+            }
+            'e' | 'E' => {
+                let mut target = self.cursor;
+                for _ in 0..count {
+                    target = word_end(buffer, target, c == 'E');
+                }
+                Some(Motion {
+                    target,
+                    inclusive: !buffer.is_empty(),
+                })
+            }
+            ';' | ',' => {
+                let (kind, target) = self.vi.as_ref()?.last_find?;
+                let kind = if c == ';' {
+                    kind
+                } else {
+                    match kind {
+                        'f' => 'F',
+                        'F' => 'f',
+                        't' => 'T',
+                        _ => 't',
+                    }
+                };
+                self.find_motion(kind, target, count, true)
+            }
+            _ => None,
+        }
+    }
+
+    /// `f`/`t` (forward) and `F`/`T` (backward) within the current line
+    fn find_motion(&self, kind: char, target: char, count: usize, repeat: bool) -> Option<Motion> {
+        let line_start = self.line_start();
+        let line_end = self.line_end();
+        let forward = matches!(kind, 'f' | 't');
+        let till = matches!(kind, 't' | 'T');
+        // `;` after `t` should not get stuck right before the target
+        let skip = usize::from(till && repeat);
+        let position = if forward {
+            (self.cursor + 1 + skip..line_end)
+                .filter(|i| self.buffer[*i] == target)
+                .nth(count - 1)?
+        } else {
+            (line_start..self.cursor.saturating_sub(skip))
+                .rev()
+                .filter(|i| self.buffer[*i] == target)
+                .nth(count - 1)?
+        };
+        Some(match (forward, till) {
+            (true, false) => Motion {
+                target: position,
+                inclusive: true,
+            },
+            (true, true) => Motion {
+                target: position - 1,
+                inclusive: true,
+            },
+            (false, false) => Motion {
+                target: position,
+                inclusive: false,
+            },
+            (false, true) => Motion {
+                target: position + 1,
+                inclusive: false,
+            },
+        })
+    }
+
+    fn vi_motion(&mut self, motion: Motion) {
+        match self.vi_mut().operator.take() {
+            None => self.cursor = motion.target.min(self.buffer.len()),
+            Some((operator, _)) => {
+                let start = self.cursor.min(motion.target);
+                let mut end = self.cursor.max(motion.target);
+                if motion.inclusive {
+                    end = (end + 1).min(self.buffer.len());
+                }
+                self.vi_apply(operator, start, end, false);
+            }
+        }
+    }
+
+    fn vi_apply(&mut self, operator: Operator, start: usize, end: usize, linewise: bool) {
+        let end = end.min(self.buffer.len());
+        let start = start.min(end);
+        let text: String = self.buffer[start..end].iter().collect();
+        if !text.is_empty() {
+            self.push_kill(text.clone());
+        }
+        let vi = self.vi_mut();
+        vi.register = text;
+        vi.linewise = linewise;
+        match operator {
+            Operator::Yank => self.cursor = start,
+            Operator::Delete | Operator::Change => {
+                self.snapshot();
+                self.buffer.drain(start..end);
+                self.cursor = start;
+                if operator == Operator::Change {
+                    self.set_vi_mode(ViMode::Insert);
+                }
+            }
+        }
+    }
+
+    /// `dd`, `cc`, `yy` (and `S`) on `count` lines
+    fn vi_lines(&mut self, operator: Operator, count: usize) {
+        let start = self.line_start();
+        let mut end = self.line_end();
+        for _ in 1..count {
+            if end >= self.buffer.len() {
+                break;
+            }
+            end = self.buffer[end + 1..]
+                .iter()
+                .position(|c| *c == '\n')
+                .map(|i| end + 1 + i)
+                .unwrap_or(self.buffer.len());
+        }
+        let text: String = self.buffer[start..end].iter().collect();
+        match operator {
+            Operator::Yank => {
+                self.vi_apply(Operator::Yank, start, end, true);
+                self.cursor = start;
+            }
+            Operator::Change => self.vi_apply(Operator::Change, start, end, true),
+            Operator::Delete => {
+                // take one of the surrounding newlines with the lines
+                let (start, end) = if end < self.buffer.len() {
+                    (start, end + 1)
+                } else {
+                    (start.saturating_sub(1), end)
+                };
+                self.vi_apply(Operator::Delete, start, end, true);
+                self.cursor = self.first_non_blank();
+            }
+        }
+        self.vi_mut().register = text;
+    }
+
+    fn vi_put(&mut self, after: bool, count: usize) {
+        let vi = self.vi_mut();
+        let (register, linewise) = (vi.register.clone(), vi.linewise);
+        if register.is_empty() {
+            return;
+        }
+        self.snapshot();
+        let text = if linewise {
+            vec![register; count].join("\n")
+        } else {
+            register.repeat(count)
+        };
+        if linewise {
+            if after {
+                self.cursor = self.line_end();
+                self.insert_str(&format!("\n{text}"));
+                self.cursor -= text.chars().count();
+            } else {
+                self.cursor = self.line_start();
+                self.insert_str(&format!("{text}\n"));
+                self.cursor -= text.chars().count() + 1;
+            }
+        } else {
+            if after && self.cursor < self.line_end() {
+                self.cursor += 1;
+            }
+            self.insert_str(&text);
+            self.cursor -= 1;
+        }
+    }
+
+    fn vi_replace(&mut self, c: char, count: usize) {
+        if self.cursor + count > self.line_end() {
+            return;
+        }
+        self.snapshot();
+        for i in self.cursor..self.cursor + count {
+            self.buffer[i] = c;
+        }
+        self.cursor += count - 1;
+    }
+
+    fn vi_toggle_case(&mut self, count: usize) {
+        let (start, end) = match self.vi_selection() {
+            Some(selection) => selection,
+            None => (self.cursor, (self.cursor + count).min(self.line_end())),
+        };
+        if start >= end {
+            return;
+        }
+        self.snapshot();
+        let toggled: Vec<char> = self.buffer[start..end]
+            .iter()
+            .flat_map(|c| {
+                if c.is_uppercase() {
+                    c.to_lowercase().collect::<Vec<_>>()
+                } else {
+                    c.to_uppercase().collect()
+                }
+            })
+            .collect();
+        // the toggled text can be longer than the original (`ß` -> `SS`)
+        let toggled_end = start + toggled.len();
+        self.buffer.splice(start..end, toggled);
+        if self.vi_mode() == Some(ViMode::Visual) {
+            self.set_vi_mode(ViMode::Normal);
+            self.cursor = start;
+        } else {
+            self.cursor = toggled_end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_editor::{PromptInfo, KILL_RING_SIZE};
+
+    fn editor(text: &str, cursor: usize) -> LineEditor {
+        let prompt = PromptInfo {
+            edit_mode: "vi".into(),
+            ..PromptInfo::default()
+        };
+        let mut editor = LineEditor::new(prompt, Default::default(), Vec::new());
+        editor.set_text(text);
+        editor.cursor = cursor;
+        editor
+    }
+
+    #[test]
+    fn toggle_case_longer_result() {
+        let mut e = editor("straße x", 4);
+        e.vi_toggle_case(3);
+        assert_eq!(e.text(), "straSSE x");
+        assert_eq!(e.cursor, 8);
+    }
+
+    #[test]
+    fn linewise_put_count() {
+        let mut e = editor("a\nb", 0);
+        e.vi_lines(Operator::Yank, 1);
+        e.vi_put(true, 2);
+        assert_eq!(e.text(), "a\na\na\nb");
+        assert_eq!(e.cursor, 2);
+    }
+
+    #[test]
+    fn operators_cap_the_kill_ring() {
+        let mut e = editor(&"x".repeat(KILL_RING_SIZE + 5), 0);
+        for _ in 0..KILL_RING_SIZE + 5 {
+            e.vi_apply(Operator::Delete, 0, 1, false);
+        }
+        assert_eq!(e.kill_ring.len(), KILL_RING_SIZE);
+    }
+}
@@ -1,6 +1,7 @@
 use heretic_nu as h;
 
 use nu_protocol::{PipelineData, ShellError, Span, Value};
+use std::ffi::OsString;
 use std::path::PathBuf;
 use std::process::exit;
 use std::sync::{Arc, Mutex};
@@ -13,82 +14,173 @@ Usages:
 * SCRIPT: heretic_nu [flags] SCRIPT_FILE_PATH ...ARGS
 * REPL:   heretic_nu [flags]
 
+Flags can be anywhere, to pass one of them to the script put it after `--`
+(`heretic_nu script.nu -- -x`).
+
 Flags:
 * -x:  debug mode
-* -xx: verbose debug mode
+* -xx | -x -x: verbose debug mode
+* --config FILE: use FILE instead of the config.nu from the config dir
+* --env-config FILE: run FILE before the config
+* --no-config-file | -n: do not load any user config (only the builtin default config)
+* --login | -l: login shell (also if argv[0] starts with `-`), additionally runs login.nu from the config dir
+* --plugin-config FILE: use FILE as plugin registry instead of plugin.msgpackz from the data dir
+* --safe-mode: if any config (env-config, config, autoload, login.nu) fails, continue with only the builtin
+  default config (or without any config if that fails as well)
+* --execute CODE | -e CODE: run CODE after loading the config and then start the REPL
 * --help | -h: show this text
 ";
 
+struct CliArgs {
+    help: bool,
+    /// 0: off, 1: `-x`, 2: `-xx`
+    debug: u8,
+    commands: Option<String>,
+    execute: Option<String>,
+    config: Option<PathBuf>,
+    env_config: Option<PathBuf>,
+    no_config_file: bool,
+    login: bool,
+    safe_mode: bool,
+    #[cfg(feature = "heretic_step_debug")]
+    step_debug_ui: Option<String>,
+    #[cfg(feature = "nu_std")]
+    no_std_lib: bool,
+    #[cfg(feature = "plugin")]
+    plugin_config: Option<PathBuf>,
+    /// script path and its arguments
+    script: Option<(PathBuf, Vec<String>)>,
+}
+
+fn parse_args(mut args: Vec<OsString>) -> Result<CliArgs, pico_args::Error> {
+    // everything after `--` belongs to the script, even flags heretic_nu knows
+    let passthrough = match args.iter().position(|arg| arg == "--") {
+        Some(i) => args.split_off(i).split_off(1),
+        None => Vec::new(),
+    };
+    let mut pargs = pico_args::Arguments::from_vec(args);
+    let mut res = CliArgs {
+        help: pargs.contains(["-h", "--help"]),
+        // `-xx` is `-x -x`
+        debug: u8::from(pargs.contains("-x")) + u8::from(pargs.contains("-x")),
+        commands: pargs.opt_value_from_str(["-c", "--commands"])?,
+        execute: pargs.opt_value_from_str(["-e", "--execute"])?,
+        config: pargs.opt_value_from_str("--config")?,
+        env_config: pargs.opt_value_from_str("--env-config")?,
+        no_config_file: pargs.contains(["-n", "--no-config-file"]),
+        login: pargs.contains(["-l", "--login"]),
+        safe_mode: pargs.contains("--safe-mode"),
+        #[cfg(feature = "heretic_step_debug")]
+        step_debug_ui: pargs.opt_value_from_str("--step-debug-ui")?,
+        #[cfg(feature = "nu_std")]
+        no_std_lib: pargs.contains("--no-std-lib"),
+        #[cfg(feature = "plugin")]
+        plugin_config: pargs.opt_value_from_str("--plugin-config")?,
+        script: None,
+    };
+    let script: Option<PathBuf> = pargs.opt_free_from_str()?;
+    let mut script_args = Vec::new();
+    for arg in pargs.finish().into_iter().chain(passthrough) {
+        script_args.push(
+            arg.into_string()
+                .map_err(|_| pico_args::Error::NonUtf8Argument)?,
+        );
+    }
+    res.script = match script {
+        Some(path) if path.to_string_lossy().starts_with('-') => {
+            return Err(pico_args::Error::ArgumentParsingFailed {
+                cause: format!("unknown argument: {}", path.display()),
+            });
+        }
+        Some(path) => Some((path, script_args)),
+        None if !script_args.is_empty() => {
+            return Err(pico_args::Error::ArgumentParsingFailed {
+                cause: "arguments after `--` need a script".into(),
+            });
+        }
+        None => None,
+    };
+    Ok(res)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     nu_command::tls::CRYPTO_PROVIDER.default();
 
     let mut nu_instance = h::NuInstance::new()?;
+    h::signals::ctrlc_protection(nu_instance.engine_state_mut());
 
-    let mut args: Vec<String> = std::env::args().skip(1).collect();
-    let mut command: Option<String> = None;
-    let mut exec_file: Option<PathBuf> = None;
-    #[cfg(feature = "nu_std")]
-    let mut use_nu_std: bool = true;
-    while !args.is_empty() {
-        let arg: String = args.remove(0);
-        match arg.as_str() {
-            "-xx" => {
-                nu_instance.engine_state.debugger =
-                    Arc::new(Mutex::new(Box::new(h::debug_x::HereticDebuggerX {
-                        log_target: h::debug_x::HereticDebuggerLogTarget::StdErr,
-                        very_verbose: true,
-                    })));
-            }
-            "-x" => {
-                nu_instance.engine_state.debugger = Arc::new(Mutex::new(Box::new(
-                    h::debug_x::HereticDebuggerX::default(),
-                )));
-            }
-            "--commands" | "-c" => {
-                if args.is_empty() {
-                    println!("'--commands' is missing argument");
-                    exit(1);
-                }
-                command = Some(args.remove(0));
-            }
-            "--help" | "-h" => {
-                println!("{HELP_TEXT}");
-                exit(0);
-            }
-            #[cfg(feature = "heretic_step_debug")]
-            "--step-debug-ui" => {
-                if args.is_empty() {
-                    println!("'--step-debug-ui' is missing argument");
-                    exit(1);
-                }
-                nu_instance.engine_state.add_env_var(
-                    "socket_dir".into(),
-                    Value::string(args.remove(0), Span::unknown()),
-                );
-                command = Some(include_str!("step_debug_server.nu").into());
-            }
-            #[cfg(feature = "nu_std")]
-            "--no-std-lib" => {
-                use_nu_std = false;
-            }
-            _ if arg.starts_with('-') => {
-                println!("Usage error: unknown argument: {arg}\n\nHelp text:\n\n{HELP_TEXT}");
-                exit(0);
-            }
-            _ => {
-                exec_file = Some(PathBuf::from(arg));
-                break;
-            }
+    let args = match parse_args(std::env::args_os().skip(1).collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Usage error: {e}\n\nHelp text:\n\n{HELP_TEXT}");
+            exit(1);
+        }
+    };
+    if args.help {
+        println!("{HELP_TEXT}");
+        exit(0);
+    }
+    match args.debug {
+        0 => {}
+        1 => {
+            nu_instance.engine_state_mut().debugger = Arc::new(Mutex::new(Box::new(
+                h::debug_x::HereticDebuggerX::default(),
+            )));
+        }
+        _ => {
+            nu_instance.engine_state_mut().debugger =
+                Arc::new(Mutex::new(Box::new(h::debug_x::HereticDebuggerX {
+                    log_target: h::debug_x::HereticDebuggerLogTarget::StdErr,
+                    very_verbose: true,
+                    ..h::debug_x::HereticDebuggerX::default()
+                })));
         }
     }
+    // `login(1)` and friends start login shells as `-heretic_nu`
+    let login = args.login
+        || std::env::args_os()
+            .next()
+            .is_some_and(|arg0| arg0.to_string_lossy().starts_with('-'));
+    if login {
+        nu_instance.set_login(true);
+    }
+    #[allow(unused_mut)]
+    let mut command = args.commands.clone();
+    #[cfg(feature = "heretic_step_debug")]
+    if let Some(socket_dir) = &args.step_debug_ui {
+        nu_instance.engine_state_mut().add_env_var(
+            "socket_dir".into(),
+            Value::string(socket_dir, Span::unknown()),
+        );
+        command = Some(include_str!("step_debug_server.nu").into());
+    }
+    // only explicitly given configs (and the login file for login shells) are loaded for
+    // scripts and `-c`
+    let explicit_configs = h::ConfigFiles {
+        env_config: args.env_config.clone(),
+        config: args.config.clone(),
+        autoload: false,
+        login: if login && !args.no_config_file {
+            h::ConfigFiles::user_login().login
+        } else {
+            None
+        },
+        safe_mode: false,
+    };
 
     #[cfg(feature = "nu_std")]
-    if use_nu_std {
+    if !args.no_std_lib {
         nu_instance.add_stdlib()?;
     }
+    #[cfg(feature = "plugin")]
+    if !args.no_config_file || args.plugin_config.is_some() {
+        nu_instance.load_plugins(args.plugin_config.clone());
+    }
 
     if let Some(script) = command {
-        nu_instance.load_default_config();
+        if !nu_instance.load_all_configs(&explicit_configs) {
+            exit(1);
+        }
         let res = nu_instance.exec(
             &script,
             Some(PipelineData::ByteStream(
@@ -101,23 +193,93 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         nu_instance.render(res);
         exit(if was_ok { 0 } else { 1 });
     }
-    if let Some(filepath) = exec_file {
-        nu_instance.load_default_config();
-        nu_instance.run_file(
-            String::from(filepath.as_os_str().to_str().unwrap()),
-            &args,
+    if let Some((filepath, script_args)) = &args.script {
+        if !nu_instance.load_all_configs(&explicit_configs) {
+            exit(1);
+        }
+        if let Err(e) = nu_instance.run_file(
+            filepath.to_string_lossy().into_owned(),
+            script_args,
             Some(PipelineData::ByteStream(
                 nu_protocol::ByteStream::stdin(Span::unknown())
                     .expect("something, something, stdin is broken"),
                 None,
             )),
-        )?;
+        ) {
+            nu_instance.render_error(&e);
+            exit(1);
+        }
         exit(0);
     }
 
-    nu_instance.load_all_configs()?;
+    let terminal = h::terminal::Terminal::acquire();
+    let config_files = if args.no_config_file {
+        h::ConfigFiles::default()
+    } else {
+        let default = if login {
+            h::ConfigFiles::user_login()
+        } else {
+            h::ConfigFiles::user_default()
+        };
+        h::ConfigFiles {
+            env_config: args.env_config.clone(),
+            config: args.config.clone().or(default.config),
+            autoload: default.autoload,
+            login: default.login,
+            safe_mode: args.safe_mode,
+        }
+    };
+    // failures are already reported, the REPL starts anyway
+    nu_instance.load_all_configs(&config_files);
+    for problem in nu_instance.validate_hooks() {
+        eprintln!("Warning: {problem}");
+    }
+    if let Some(code) = &args.execute {
+        let res = nu_instance.exec(code, None);
+        nu_instance.render(res);
+    }
+
+    let history_enabled = match nu_instance.open_history() {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Error: failed to open history (history will not be saved): {e}");
+            false
+        }
+    };
+
+    let mut config_stamp = nu_instance.config_stamp(&config_files);
 
     loop {
+        if let Some(terminal) = &terminal {
+            terminal.reclaim();
+        }
+        nu_instance.reset_signals();
+        let mut reload = nu_instance.take_config_reload_request();
+        if !reload && nu_instance.config_watch_enabled() {
+            let stamp = nu_instance.config_stamp(&config_files);
+            if stamp != config_stamp {
+                eprintln!("Config changed, reloading");
+                reload = true;
+            }
+        }
+        if reload {
+            if nu_instance.reload_configs(&config_files) {
+                for problem in nu_instance.validate_hooks() {
+                    eprintln!("Warning: {problem}");
+                }
+            }
+            // also after a failed reload, so a broken file is not retried before every prompt
+            config_stamp = nu_instance.config_stamp(&config_files);
+        }
+        // same order as upstream: pre_prompt, env_change, prompt
+        if let Err(e) = nu_instance.run_pre_prompt_hooks() {
+            eprintln!("Error in pre_prompt hook:");
+            nu_instance.render_error(&e);
+        }
+        if let Err(e) = nu_instance.run_env_change_hooks() {
+            eprintln!("Error in env_change hook:");
+            nu_instance.render_error(&e);
+        }
         match nu_instance.exec("_heretic_nu_prompt", None) {
             Ok(PipelineData::Value(Value::String { val, .. }, _)) => {
                 print!("{}", val);
@@ -143,38 +305,62 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             Ok(PipelineData::Empty) => {}
             Err(e) => {
-                eprintln!("Error in _heretic_nu_prompt: {e}");
+                eprintln!("Error in _heretic_nu_prompt:");
+                nu_instance.render_error(&e);
                 print!("> ");
             }
         }
         let input: String = match nu_instance.exec("_heretic_nu_input", None) {
             Ok(PipelineData::Value(Value::String { val, .. }, _)) => val,
+            // the user wants to exit (ctrl-d)
+            Ok(PipelineData::Value(Value::Nothing { .. }, _)) | Ok(PipelineData::Empty) => break,
             Ok(_) => {
-                eprintln!("Error: invalid _heretic_nu_input return type (not a string)");
+                eprintln!("Error: invalid _heretic_nu_input return type (not a string or nothing)");
                 std::process::exit(1);
             }
             Err(e) => {
-                eprintln!("Error in _heretic_nu_input: {e}");
+                eprintln!("Error in _heretic_nu_input:");
+                nu_instance.render_error(&e);
                 std::process::exit(1);
             }
         };
+        if let Err(e) = nu_instance.run_pre_execution_hooks() {
+            eprintln!("Error in pre_execution hook:");
+            nu_instance.render_error(&e);
+        }
         nu_instance.set_interactive(true);
+        let start_timestamp = chrono::Local::now().fixed_offset();
+        let cwd = nu_instance
+            .engine_state()
+            .cwd(Some(nu_instance.stack()))
+            .map(|cwd| cwd.into_std_path_buf().to_string_lossy().into_owned())
+            .unwrap_or_default();
+        nu_instance.reset_signals();
         let start_time = std::time::Instant::now();
-        let res = nu_instance.exec(&input, None);
-        nu_instance.engine_state.add_env_var(
-            "CMD_DURATION_MS".into(),
-            Value::string(
-                format!("{}", start_time.elapsed().as_millis()),
-                Span::unknown(),
-            ),
-        );
-        let exitcode: (i32, Span) = match res {
+        let res = nu_instance
+            .exec(&input, None)
+            .and_then(|pipeline_data| pipeline_data.into_value(Span::unknown()));
+        let duration = start_time.elapsed();
+        let exitcode: (i32, Span) = match &res {
             Ok(_) => (0, Span::unknown()),
-            Err(ShellError::NonZeroExitCode { exit_code, span }) => (exit_code.get(), span),
+            Err(ShellError::NonZeroExitCode { exit_code, span }) => (exit_code.get(), *span),
             Err(_) => (1, Span::unknown()),
         };
         nu_instance.set_exitcode(exitcode.0, exitcode.1);
+        nu_instance.set_last_result(&input, &res, exitcode.0, start_timestamp, duration);
+        if history_enabled && !input.trim().is_empty() {
+            if let Err(e) = nu_instance.push_history(h::history::HistoryEntry {
+                command: input.clone(),
+                start: start_timestamp,
+                duration,
+                exit_status: exitcode.0 as i64,
+                cwd,
+            }) {
+                eprintln!("Error: failed to save history: {e}");
+            }
+        }
         nu_instance.set_interactive(false);
-        nu_instance.render(res);
+        nu_instance.render(res.map(|value| PipelineData::Value(value, None)));
     }
+    Ok(())
 }
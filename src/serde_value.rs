@@ -0,0 +1,831 @@
+//! conversion between rust types (serde) and nu values, without a detour through nuon.
+//!
+//! * structs and maps are records, sequences and tuples are lists, `None` and `()` are `null`
+//! * unit enum variants are strings, other variants `{variant: content}` records
+//! * nu ints are `i64` (bigger rust ints fail), filesizes and durations become ints,
+//!   dates rfc3339 strings
+//! * map keys can be strings, chars, ints, bools, unit variants, and newtypes/`Some` of them:
+//!   record keys are strings, so the others are stringified (and parsed back). `None` and
+//!   float keys fail
+
+use std::fmt::Display;
+
+use nu_protocol::{Record, ShellError, Span, Value};
+use serde::{
+    de::{self, DeserializeSeed, Visitor},
+    ser, Deserialize, Serialize,
+};
+
+/// convert a rust value into a nu value (all parts get `span`)
+#[allow(clippy::result_large_err)]
+pub fn to_value<T: Serialize + ?Sized>(value: &T, span: Span) -> Result<Value, ShellError> {
+    value
+        .serialize(ValueSerializer { span })
+        .map_err(|e| ShellError::GenericError {
+            error: "Failed to convert to a nu value".into(),
+            msg: e.0,
+            span: Some(span),
+            help: None,
+            inner: vec![],
+        })
+}
+
+/// convert a nu value into a rust value
+#[allow(clippy::result_large_err)]
+pub fn from_value<'de, T: Deserialize<'de>>(value: &'de Value) -> Result<T, ShellError> {
+    T::deserialize(ValueDeserializer { value }).map_err(|e| ShellError::CantConvert {
+        to_type: std::any::type_name::<T>().into(),
+        from_type: value.get_type().to_string(),
+        span: value.span(),
+        help: Some(e.0),
+    })
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// `{variant: value}` for enum variants with content
+fn wrap_variant(variant: Option<&'static str>, value: Value, span: Span) -> Value {
+    match variant {
+        Some(variant) => {
+            let mut record = Record::new();
+            record.push(variant, value);
+            Value::record(record, span)
+        }
+        None => value,
+    }
+}
+
+fn int<T: TryInto<i64> + Display + Copy>(v: T, span: Span) -> Result<Value, Error> {
+    v.try_into()
+        .map(|v| Value::int(v, span))
+        .map_err(|_| Error(format!("{v} does not fit into a nu int (i64)")))
+}
+
+struct ValueSerializer {
+    span: Span,
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::bool(v, self.span))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, Error> {
+        int(v, self.span)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::float(v.into(), self.span))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::float(v, self.span))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::string(v.to_string(), self.span))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::string(v, self.span))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::binary(v, self.span))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::nothing(self.span))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::nothing(self.span))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::nothing(self.span))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(Value::string(variant, self.span))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let span = self.span;
+        Ok(wrap_variant(Some(variant), value.serialize(self)?, span))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            span: self.span,
+            variant: None,
+            vals: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer {
+            span: self.span,
+            variant: Some(variant),
+            vals: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            span: self.span,
+            variant: None,
+            record: Record::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer {
+            span: self.span,
+            variant: Some(variant),
+            record: Record::new(),
+            key: None,
+        })
+    }
+}
+
+struct SeqSerializer {
+    span: Span,
+    variant: Option<&'static str>,
+    vals: Vec<Value>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.vals
+            .push(value.serialize(ValueSerializer { span: self.span })?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let list = Value::list(self.vals, self.span);
+        Ok(wrap_variant(self.variant, list, self.span))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    span: Span,
+    variant: Option<&'static str>,
+    record: Record,
+    /// the key of the next `serialize_value`
+    key: Option<String>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        let value = value.serialize(ValueSerializer { span: self.span })?;
+        self.record.insert(key, value);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let record = Value::record(self.record, self.span);
+        Ok(wrap_variant(self.variant, record, self.span))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        // record keys are strings, ints and bools (and unit variants) are converted
+        self.key = Some(match key.serialize(ValueSerializer { span: self.span })? {
+            Value::String { val, .. } => val,
+            Value::Int { val, .. } => val.to_string(),
+            Value::Bool { val, .. } => val.to_string(),
+            other => {
+                return Err(Error(format!(
+                    "record keys have to be strings, got {}",
+                    other.get_type()
+                )));
+            }
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error("map value serialized before its key".into()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.into(), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.into(), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+struct ValueDeserializer<'de> {
+    value: &'de Value,
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Bool { val, .. } => visitor.visit_bool(*val),
+            Value::Int { val, .. } => visitor.visit_i64(*val),
+            Value::Float { val, .. } => visitor.visit_f64(*val),
+            Value::Filesize { val, .. } => visitor.visit_i64(val.get()),
+            Value::Duration { val, .. } => visitor.visit_i64(*val),
+            Value::String { val, .. } | Value::Glob { val, .. } => visitor.visit_borrowed_str(val),
+            Value::Date { val, .. } => visitor.visit_string(val.to_rfc3339()),
+            Value::Binary { val, .. } => visitor.visit_borrowed_bytes(val),
+            Value::List { vals, .. } => visitor.visit_seq(SeqDeserializer { iter: vals.iter() }),
+            Value::Record { val, .. } => visitor.visit_map(MapDeserializer {
+                iter: val.iter(),
+                value: None,
+            }),
+            Value::Nothing { .. } => visitor.visit_unit(),
+            other => Err(Error(format!(
+                "a {} can not be converted into a rust value",
+                other.get_type()
+            ))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Nothing { .. } => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.value {
+            Value::String { val, .. } => {
+                let variant: de::value::BorrowedStrDeserializer<'de, Error> =
+                    de::value::BorrowedStrDeserializer::new(val);
+                visitor.visit_enum(variant)
+            }
+            Value::Record { val, .. } => {
+                let mut iter = val.iter();
+                match (iter.next(), iter.next()) {
+                    (Some((variant, value)), None) => {
+                        visitor.visit_enum(EnumDeserializer { variant, value })
+                    }
+                    _ => Err(Error(
+                        "enum variants with content have to be records with exactly one key".into(),
+                    )),
+                }
+            }
+            other => Err(Error(format!(
+                "enum variants have to be strings or records, got {}",
+                other.get_type()
+            ))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqDeserializer<I> {
+    iter: I,
+}
+
+impl<'de, I: Iterator<Item = &'de Value>> de::SeqAccess<'de> for SeqDeserializer<I> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.iter
+            .next()
+            .map(|value| seed.deserialize(ValueDeserializer { value }))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        }
+    }
+}
+
+struct MapDeserializer<'de, I> {
+    iter: I,
+    /// the value of the last key
+    value: Option<&'de Value>,
+}
+
+impl<'de, I: Iterator<Item = (&'de String, &'de Value)>> de::MapAccess<'de>
+    for MapDeserializer<'de, I>
+{
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(KeyDeserializer { key }).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| Error("map value requested before its key".into()))?;
+        seed.deserialize(ValueDeserializer { value })
+    }
+}
+
+/// record keys are strings, but maps with int or bool keys are serialized with stringified
+/// keys, so those are parsed back
+struct KeyDeserializer<'de> {
+    key: &'de str,
+}
+
+macro_rules! deserialize_int_key {
+    ($($method:ident => $visit:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.key.parse() {
+                    Ok(int) => visitor.$visit(int),
+                    Err(_) => visitor.visit_borrowed_str(self.key),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_str(self.key)
+    }
+
+    deserialize_int_key! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.key.parse() {
+            Ok(bool) => visitor.visit_bool(bool),
+            Err(_) => visitor.visit_borrowed_str(self.key),
+        }
+    }
+
+    /// a key is never `null` (`None` keys can not be serialized)
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let variant: de::value::BorrowedStrDeserializer<'de, Error> =
+            de::value::BorrowedStrDeserializer::new(self.key);
+        visitor.visit_enum(variant)
+    }
+
+    serde::forward_to_deserialize_any! {
+        f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct EnumDeserializer<'de> {
+    variant: &'de String,
+    value: &'de Value,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer<'de> {
+    type Error = Error;
+    type Variant = ValueDeserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, ValueDeserializer<'de>), Error> {
+        let variant: de::value::BorrowedStrDeserializer<'de, Error> =
+            de::value::BorrowedStrDeserializer::new(self.variant);
+        Ok((
+            seed.deserialize(variant)?,
+            ValueDeserializer { value: self.value },
+        ))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for ValueDeserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        <()>::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use nu_protocol::Filesize;
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(value: T) {
+        let nu_value = to_value(&value, Span::test_data()).unwrap();
+        assert_eq!(from_value::<T>(&nu_value).unwrap(), value);
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Job {
+        name: String,
+        pids: Vec<u32>,
+        exit_code: Option<i64>,
+        tags: BTreeMap<String, Vec<String>>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Shape {
+        Unit,
+        Newtype(i64),
+        Tuple(i64, String),
+        Struct { width: f64, height: f64 },
+    }
+
+    #[test]
+    fn structs() {
+        round_trip(Job {
+            name: "vim".into(),
+            pids: vec![1, 2],
+            exit_code: None,
+            tags: BTreeMap::from([("a".into(), vec!["b".into()])]),
+        });
+        let value = to_value(
+            &Job {
+                name: "vim".into(),
+                pids: vec![],
+                exit_code: Some(1),
+                tags: BTreeMap::new(),
+            },
+            Span::test_data(),
+        )
+        .unwrap();
+        assert_eq!(
+            value.as_record().unwrap().get("exit_code"),
+            Some(&Value::test_int(1))
+        );
+    }
+
+    #[test]
+    fn options() {
+        round_trip(Some(1i64));
+        round_trip(None::<i64>);
+        round_trip(vec![Some("a".to_string()), None]);
+        assert_eq!(
+            to_value(&None::<i64>, Span::test_data()).unwrap(),
+            Value::test_nothing()
+        );
+    }
+
+    #[test]
+    fn enums() {
+        round_trip(Shape::Unit);
+        round_trip(Shape::Newtype(3));
+        round_trip(Shape::Tuple(3, "x".into()));
+        round_trip(Shape::Struct {
+            width: 1.5,
+            height: 2.0,
+        });
+        assert_eq!(
+            to_value(&Shape::Unit, Span::test_data()).unwrap(),
+            Value::test_string("Unit")
+        );
+        let newtype = to_value(&Shape::Newtype(3), Span::test_data()).unwrap();
+        assert_eq!(
+            newtype.as_record().unwrap().get("Newtype"),
+            Some(&Value::test_int(3))
+        );
+    }
+
+    #[test]
+    fn nested() {
+        round_trip(vec![vec![Shape::Unit, Shape::Newtype(1)], vec![]]);
+        round_trip(BTreeMap::from([(
+            "outer".to_string(),
+            BTreeMap::from([("inner".to_string(), vec![(1i64, true)])]),
+        )]));
+    }
+
+    #[test]
+    fn other_map_keys() {
+        #[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug)]
+        struct Id(u32);
+
+        round_trip(BTreeMap::from([(true, 1i64), (false, 2)]));
+        round_trip(BTreeMap::from([(Some(3i64), 1i64)]));
+        round_trip(BTreeMap::from([(Some("a".to_string()), 1i64)]));
+        round_trip(BTreeMap::from([(Id(4), "a".to_string())]));
+        round_trip(BTreeMap::from([('x', 1i64)]));
+        assert!(to_value(&BTreeMap::from([(None::<i64>, 1i64)]), Span::test_data()).is_err());
+    }
+
+    #[test]
+    fn int_map_keys() {
+        round_trip(HashMap::from([(1i64, "a".to_string()), (-2, "b".into())]));
+        round_trip(BTreeMap::from([(7u8, 1i64)]));
+        let value = to_value(&BTreeMap::from([(1i64, true)]), Span::test_data()).unwrap();
+        assert_eq!(
+            value.as_record().unwrap().get("1"),
+            Some(&Value::test_bool(true))
+        );
+    }
+
+    #[test]
+    fn int_overflow() {
+        assert!(to_value(&u64::MAX, Span::test_data()).is_err());
+        assert!(to_value(&(i64::MAX as i128 + 1), Span::test_data()).is_err());
+        round_trip(i64::MAX as u64);
+        round_trip(i64::MIN as i128);
+        assert!(from_value::<u64>(&Value::test_int(-1)).is_err());
+        assert!(from_value::<u8>(&Value::test_int(256)).is_err());
+    }
+
+    #[test]
+    fn filesize_duration_date() {
+        let filesize = Value::filesize(Filesize::new(1024), Span::test_data());
+        assert_eq!(from_value::<i64>(&filesize).unwrap(), 1024);
+        let duration = Value::duration(5_000_000_000, Span::test_data());
+        assert_eq!(from_value::<i64>(&duration).unwrap(), 5_000_000_000);
+        let date = chrono::DateTime::parse_from_rfc3339("2024-01-02T03:04:05+02:00").unwrap();
+        assert_eq!(
+            from_value::<String>(&Value::date(date, Span::test_data())).unwrap(),
+            "2024-01-02T03:04:05+02:00"
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use nu_protocol::{engine::EngineState, Handlers, SignalAction, Signals};
+
+/// Make ctrl-c interrupt the currently running pipeline instead of killing the shell.
+/// Same semantics as upstream nu: the interrupt flag gets checked by the running commands.
+/// The terminal sends SIGINT to its foreground process group: while a external command runs,
+/// that is the command's own process group (see `terminal`), so the command gets it directly,
+/// otherwise it is the shell's group and this handler sets the flag.
+///
+/// Can only be called once per process.
+pub fn ctrlc_protection(engine_state: &mut EngineState) {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    engine_state.set_signals(Signals::new(interrupt.clone()));
+
+    let signal_handlers = Handlers::new();
+    engine_state.signal_handlers = Some(signal_handlers.clone());
+
+    ctrlc::set_handler(move || {
+        interrupt.store(true, Ordering::Relaxed);
+        signal_handlers.run(SignalAction::Interrupt);
+    })
+    .expect("Error setting Ctrl-C handler");
+}
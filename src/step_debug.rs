@@ -1,6 +1,9 @@
-use nu_protocol::{debugger::Debugger, Span, Value};
+use nu_protocol::{debugger::Debugger, ShellError, Span, Value};
 
-use crate::NuInstance;
+use crate::{
+    debug_x::{create_log_file, log_file, new_log_id, DebugSession, HereticDebuggerLogTarget},
+    NuInstance,
+};
 
 const MAX_SOCKET_DIR_PATH_LENGTH: usize = 512;
 
@@ -10,19 +13,29 @@ const HEADER: &str = "\x1b[1;37;45m";
 // TODO: send over actual structured data in addition to render
 // TODO: recieve commands back (update variables, etc)
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct HereticStepDebugger {
     socket_dir: Option<[char; MAX_SOCKET_DIR_PATH_LENGTH]>,
+    /// every step is also written to this log-file (in `dirs::debug_log_dir`)
+    log_id: Option<u64>,
+    session: DebugSession,
 }
 
 impl HereticStepDebugger {
-    fn send_to_server(&self, text: String) {
-        let mut ni = NuInstance::new().expect("Failed to create new NU instance");
-        ni.engine_state.add_env_var(
+    #[allow(clippy::result_large_err)]
+    fn send_to_server(&self, text: String) -> Result<(), ShellError> {
+        let socket_dir = self.socket_dir.ok_or_else(|| ShellError::GenericError {
+            error: "Step-debugger is not connected".into(),
+            msg: "the debugger ui was not started".into(),
+            span: None,
+            help: None,
+            inner: vec![],
+        })?;
+        let mut ni = NuInstance::new()?;
+        ni.engine_state_mut().add_env_var(
             "sock_dir".into(),
             Value::string(
-                self.socket_dir
-                    .expect("called debugger step before activate (HereticStepDebugger)")
+                socket_dir
                     .iter()
                     .filter(|i| **i != '\0')
                     .collect::<String>(),
@@ -35,49 +48,44 @@ impl HereticStepDebugger {
                 Value::string(text, Span::unknown()),
                 None,
             )),
-        )
-        .expect("Failed to run step_debug_client.nu");
+        )?;
+        Ok(())
     }
-}
 
-impl Debugger for HereticStepDebugger {
-    fn activate(&mut self) {
-        let socket_dir: String = if let Some(res) = match NuInstance::new()
-            .expect("Failed to create new NU instance")
-            .exec(
-                r#"
-                    let sock_dir = (mktemp --directory)
-                    touch ($sock_dir | path join 'no_data_lock.bin')
-                    def is_installed [app: string]: nothing -> bool {
-                        (which $app).0?.path? != null
-                    }
-                    if (is_installed 'wezterm') {
-                        job spawn { ^wezterm start --always-new-process --no-auto-connect heretic_nu --step-debug-ui $sock_dir }
-                    } else {
-                        print --stderr 'Failed to find a terminal-emulator'
-                        exit 1
-                    }
-                    return $sock_dir
-                "#,
-                None,
-            )
-            .expect(
-                "Failed to launch the HereticStepDebugger ui app in a new terminal (nu-code-error)",
-            ) {
-            nu_protocol::PipelineData::Value(value, ..) => match value {
-                nu_protocol::Value::String { val, .. } => Some(val),
-                _ => None,
-            },
-            nu_protocol::PipelineData::Empty
-            | nu_protocol::PipelineData::ListStream(..)
-            | nu_protocol::PipelineData::ByteStream(..) => None,
-        } {
-            res
-        } else {
-            panic!("Failed to launch the HereticStepDebugger ui app in a new terminal (did not return a socket_dir)");
+    /// open the ui in a new terminal, returns the socket dir
+    #[allow(clippy::result_large_err)]
+    fn launch_ui() -> Result<[char; MAX_SOCKET_DIR_PATH_LENGTH], ShellError> {
+        let launch_error = |msg: String| ShellError::GenericError {
+            error: "Failed to launch the HereticStepDebugger ui app in a new terminal".into(),
+            msg,
+            span: None,
+            help: None,
+            inner: vec![],
+        };
+        let socket_dir = match NuInstance::new()?.exec(
+            r#"
+                let sock_dir = (mktemp --directory)
+                touch ($sock_dir | path join 'no_data_lock.bin')
+                def is_installed [app: string]: nothing -> bool {
+                    (which $app).0?.path? != null
+                }
+                if (is_installed 'wezterm') {
+                    job spawn { ^wezterm start --always-new-process --no-auto-connect heretic_nu --step-debug-ui $sock_dir }
+                } else {
+                    print --stderr 'Failed to find a terminal-emulator'
+                    exit 1
+                }
+                return $sock_dir
+            "#,
+            None,
+        )? {
+            nu_protocol::PipelineData::Value(Value::String { val, .. }, ..) => val,
+            _ => return Err(launch_error("did not return a socket_dir".into())),
         };
         if socket_dir.chars().count() >= MAX_SOCKET_DIR_PATH_LENGTH {
-            panic!("Socket dir path is to long (>= {MAX_SOCKET_DIR_PATH_LENGTH})");
+            return Err(launch_error(format!(
+                "socket dir path is to long (>= {MAX_SOCKET_DIR_PATH_LENGTH})"
+            )));
         }
         let mut sda: [char; MAX_SOCKET_DIR_PATH_LENGTH] = ['\0'; MAX_SOCKET_DIR_PATH_LENGTH];
         let mut cs = socket_dir.chars();
@@ -85,10 +93,34 @@ impl Debugger for HereticStepDebugger {
         for i in 0..MAX_SOCKET_DIR_PATH_LENGTH {
             sda[i] = cs.next().unwrap_or('\0');
         }
-        self.socket_dir = Some(sda);
+        Ok(sda)
     }
+}
 
-    fn deactivate(&mut self) {}
+impl Debugger for HereticStepDebugger {
+    fn activate(&mut self) {
+        self.session.start();
+        let log_id = new_log_id();
+        self.log_id = match create_log_file(log_id) {
+            Ok(_) => Some(log_id),
+            Err(e) => {
+                eprintln!("Error: failed to create the debug log-file: {e}");
+                None
+            }
+        };
+        // the trait can not return errors, they end up in the report
+        match Self::launch_ui() {
+            Ok(socket_dir) => self.socket_dir = Some(socket_dir),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                self.session.error(&e, Span::unknown());
+            }
+        }
+    }
+
+    fn deactivate(&mut self) {
+        self.session.stop();
+    }
 
     #[allow(unused_variables)]
     fn enter_block(
@@ -96,6 +128,7 @@ impl Debugger for HereticStepDebugger {
         engine_state: &nu_protocol::engine::EngineState,
         block: &nu_protocol::ast::Block,
     ) {
+        self.session.enter_block();
     }
 
     #[allow(unused_variables)]
@@ -104,6 +137,7 @@ impl Debugger for HereticStepDebugger {
         engine_state: &nu_protocol::engine::EngineState,
         block: &nu_protocol::ast::Block,
     ) {
+        self.session.leave_block();
     }
 
     #[allow(unused_variables)]
@@ -112,6 +146,7 @@ impl Debugger for HereticStepDebugger {
         engine_state: &nu_protocol::engine::EngineState,
         pipeline_element: &nu_protocol::ast::PipelineElement,
     ) {
+        self.session.enter_element();
     }
 
     #[allow(unused_variables)]
@@ -121,6 +156,10 @@ impl Debugger for HereticStepDebugger {
         element: &nu_protocol::ast::PipelineElement,
         result: &Result<nu_protocol::PipelineData, nu_protocol::ShellError>,
     ) {
+        self.session.leave_element();
+        if let Err(err) = result {
+            self.session.error(err, element.expr.span);
+        }
     }
 
     #[allow(unused_variables)]
@@ -131,7 +170,11 @@ impl Debugger for HereticStepDebugger {
         instruction_index: usize,
         registers: &[nu_protocol::PipelineExecutionData],
     ) {
-        self.send_to_server(format!(
+        self.session.enter_instruction();
+        if self.socket_dir.is_none() && self.log_id.is_none() {
+            return;
+        }
+        let text = format!(
             "\
                 {HEADER}  <=== ENV ===>  {RESET}\n\
                 {env_vars}\n\
@@ -148,7 +191,16 @@ impl Debugger for HereticStepDebugger {
             ir = render_ir(engine_state, ir_block, instruction_index),
             registers = render_registers(registers),
             env_vars = render_env_vars(engine_state),
-        ));
+        );
+        if let Some(id) = self.log_id {
+            HereticDebuggerLogTarget::LogDir(id).log(&text);
+        }
+        if self.socket_dir.is_none() {
+            return;
+        }
+        if let Err(e) = self.send_to_server(text) {
+            self.session.error(&e, ir_block.spans[instruction_index]);
+        }
     }
 
     #[allow(unused_variables)]
@@ -160,16 +212,23 @@ impl Debugger for HereticStepDebugger {
         registers: &[nu_protocol::PipelineExecutionData],
         error: Option<&nu_protocol::ShellError>,
     ) {
-        let nu_inst = NuInstance::new();
+        self.session.leave_instruction();
+        if let Some(err) = error {
+            self.session.error(err, ir_block.spans[instruction_index]);
+        }
     }
 
-    #[allow(unused_variables)]
     fn report(
         &self,
         engine_state: &nu_protocol::engine::EngineState,
         debugger_span: nu_protocol::Span,
     ) -> Result<nu_protocol::Value, nu_protocol::ShellError> {
-        Ok(nu_protocol::Value::nothing(debugger_span))
+        Ok(self.session.report(
+            engine_state,
+            "step",
+            self.log_id.map(log_file),
+            debugger_span,
+        ))
     }
 }
 
@@ -229,3 +288,26 @@ fn render_env_vars(engine_state: &nu_protocol::engine::EngineState) -> String {
         .collect::<Vec<String>>()
         .join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_log_file() {
+        let debugger = HereticStepDebugger {
+            log_id: Some(42),
+            ..HereticStepDebugger::default()
+        };
+        let ni = NuInstance::new().unwrap();
+        let report = debugger
+            .report(ni.engine_state(), Span::test_data())
+            .unwrap();
+        let report = report.as_record().unwrap();
+        assert_eq!(report.get("debugger"), Some(&Value::test_string("step")));
+        assert_eq!(
+            report.get("log_file"),
+            Some(&Value::test_string(log_file(42).to_string_lossy()))
+        );
+    }
+}
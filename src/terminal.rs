@@ -0,0 +1,130 @@
+//! terminal ownership for job control (the same dance upstream nu does on startup).
+//!
+//! the shell gets its own process group and becomes the foreground process group of the
+//! terminal, external commands are started in their own process groups (by nu, because
+//! `is_interactive` is set while running REPL input) and get the terminal handed over.
+//! stopping them (ctrl-z) turns them into frozen jobs (`job list`, `job unfreeze`).
+
+/// terminal state of the shell, restored after every command
+pub struct Terminal {
+    #[cfg(unix)]
+    shell_pgid: nix::unistd::Pid,
+    #[cfg(unix)]
+    termios: Option<nix::sys::termios::Termios>,
+}
+
+#[cfg(unix)]
+impl Terminal {
+    /// take control of the terminal (`None` if stdin is not a terminal)
+    pub fn acquire() -> Option<Self> {
+        use std::io::IsTerminal;
+
+        use nix::{
+            sys::signal::{killpg, signal, SigHandler, Signal},
+            unistd::{getpgrp, getpid, setpgid, tcgetpgrp, tcsetpgrp},
+        };
+
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            return None;
+        }
+
+        // wait until we are in the foreground (a job-control shell might have started us in
+        // the background)
+        let mut attempts = 0;
+        while tcgetpgrp(&stdin).ok()? != getpgrp() {
+            if attempts >= 4096 {
+                eprintln!("Error: failed to take control of the terminal (we might be orphaned)");
+                return None;
+            }
+            let _ = killpg(getpgrp(), Signal::SIGTTIN);
+            attempts += 1;
+        }
+
+        // SAFETY: ignoring signals does not run any code in the signal handler
+        unsafe {
+            let _ = signal(Signal::SIGQUIT, SigHandler::SigIgn);
+            let _ = signal(Signal::SIGTSTP, SigHandler::SigIgn);
+            let _ = signal(Signal::SIGTTIN, SigHandler::SigIgn);
+            let _ = signal(Signal::SIGTTOU, SigHandler::SigIgn);
+        }
+
+        let shell_pgid = getpid();
+        // fails if we already are a session leader (login shell), which is fine
+        let _ = setpgid(shell_pgid, shell_pgid);
+        if let Err(e) = tcsetpgrp(&stdin, shell_pgid) {
+            eprintln!("Error: failed to take control of the terminal: {e}");
+        }
+
+        Some(Self {
+            shell_pgid,
+            termios: nix::sys::termios::tcgetattr(&stdin).ok(),
+        })
+    }
+
+    /// get the terminal back after a command (a stopped program might still own it or
+    /// have left it in raw mode)
+    pub fn reclaim(&self) {
+        use nix::{
+            sys::termios::{tcsetattr, SetArg},
+            unistd::{tcgetpgrp, tcsetpgrp},
+        };
+
+        let stdin = std::io::stdin();
+        if tcgetpgrp(&stdin).is_ok_and(|pgid| pgid != self.shell_pgid) {
+            let _ = tcsetpgrp(&stdin, self.shell_pgid);
+        }
+        if let Some(termios) = &self.termios {
+            let _ = tcsetattr(&stdin, SetArg::TCSADRAIN, termios);
+        }
+    }
+}
+
+/// continue a stopped job in the background (`heretic bg`): every external command runs in
+/// its own process group, led by its first process
+#[cfg(unix)]
+pub fn continue_process_group(pid: u32) -> nix::Result<()> {
+    use nix::{
+        sys::signal::{killpg, Signal},
+        unistd::Pid,
+    };
+
+    killpg(Pid::from_raw(pid as i32), Signal::SIGCONT)
+}
+
+/// state of a job continued by `heretic bg`
+#[cfg(unix)]
+pub enum ProcessState {
+    Running,
+    Stopped,
+    /// finished (and reaped)
+    Gone,
+}
+
+/// check on a job continued in the background without waiting for it
+#[cfg(unix)]
+pub fn poll_process(pid: u32) -> ProcessState {
+    use nix::{
+        sys::wait::{waitpid, WaitPidFlag, WaitStatus},
+        unistd::Pid,
+    };
+
+    match waitpid(
+        Pid::from_raw(pid as i32),
+        Some(WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED),
+    ) {
+        Ok(WaitStatus::StillAlive | WaitStatus::Continued(_)) => ProcessState::Running,
+        Ok(WaitStatus::Stopped(..)) => ProcessState::Stopped,
+        _ => ProcessState::Gone,
+    }
+}
+
+#[cfg(not(unix))]
+impl Terminal {
+    /// job control is only supported on unix
+    pub fn acquire() -> Option<Self> {
+        None
+    }
+
+    pub fn reclaim(&self) {}
+}